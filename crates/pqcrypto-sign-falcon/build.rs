@@ -71,9 +71,17 @@ fn ntt_constants(n: usize) -> (Vec<Vec<u16>>, Vec<u16>) {
     (roots, inverses)
 }
 
+/// Twiddle of block b at the level with m blocks of the in-place FFT, stored at m + b
+fn fft_twiddles(roots: &[Vec<Complex64>]) -> Vec<Complex64> {
+    std::iter::once(Complex64::new(0.0, 0.0))
+        .chain(roots.iter().flat_map(|r| r.iter().step_by(2).copied()))
+        .collect()
+}
+
 fn save_fft_constants(out_dir: &OsStr) {
     let dest_path = Path::new(&out_dir).join("fft_constants.rs");
     let roots = fft_constants(9);
+    let twiddles = fft_twiddles(&roots);
 
     let align_out = " ".repeat(8);
     let align_in = " ".repeat(12);
@@ -93,16 +101,29 @@ fn save_fft_constants(out_dir: &OsStr) {
         .collect::<Vec<_>>()
         .join(&format!("\n{align_out}"));
 
+    let twiddles_len = twiddles.len();
+    let twiddles = twiddles
+        .into_iter()
+        .map(|c| format!("Complex64 {{ re: {}f64, im: {}f64 }},", c.re, c.im))
+        .collect::<Vec<_>>()
+        .join(&format!("\n{}", " ".repeat(4)));
+
     let content = format!(
         "\
 use num_complex::Complex64;
 
-const pub fn roots(n: u16) -> &'static [Complex64] {{
+#[allow(clippy::approx_constant)]
+pub const fn roots(n: u16) -> &'static [Complex64] {{
     match n {{
         {arms}
         _ => unreachable!(),
     }}
-}}"
+}}
+
+#[allow(clippy::approx_constant)]
+static FFT_TWIDDLES: [Complex64; {twiddles_len}] = [
+    {twiddles}
+];"
     );
     fs::write(&dest_path, content).unwrap();
 }
@@ -120,7 +141,8 @@ fn save_ntt_constants(out_dir: &OsStr) {
 
     let function = format!(
         "\
-pub fn roots_Zq(n: u16) -> &'static [u16] {{
+#[allow(non_snake_case)]
+pub const fn roots_Zq(n: u16) -> &'static [u16] {{
     match n {{
         {arms}
         _ => unreachable!(),
@@ -128,7 +150,7 @@ pub fn roots_Zq(n: u16) -> &'static [u16] {{
 }}"
     );
 
-    let inverses = format!("const INV_MOD_Q: [u16; {}] = {inverses:?};", inverses.len());
+    let inverses = format!("static INV_MOD_Q: [u16; {}] = {inverses:?};", inverses.len());
 
     fs::write(
         &dest_path,
@@ -142,9 +164,74 @@ pub fn roots_Zq(n: u16) -> &'static [u16] {{
     .unwrap();
 }
 
+/// Montgomery factor 2^16 of the in-place NTT
+const MONTGOMERY_R: u32 = 1 << 16;
+/// Primitive 2^(MAX_LOGN + 1)-th root of unity mod q
+const PSI: u32 = 7;
+const MAX_LOGN: u32 = 10;
+
+fn pow_mod(base: u32, mut exponent: u32) -> u32 {
+    let q = Q as u32;
+    let mut base = base % q;
+    let mut result = 1;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result * base % q;
+        }
+        base = base * base % q;
+        exponent >>= 1;
+    }
+    result
+}
+
+fn to_montgomery(x: u32) -> u16 {
+    (x * MONTGOMERY_R % Q as u32) as u16
+}
+
+/// Powers psi^rev(k) and psi^-rev(k) in Montgomery form, rev reversing MAX_LOGN bits
+fn ntt_twiddles() -> (Vec<u16>, Vec<u16>) {
+    let n = 1u32 << MAX_LOGN;
+    let psi_inv = pow_mod(PSI, 2 * n - 1);
+    (0..n)
+        .map(|k| {
+            let e = k.reverse_bits() >> (u32::BITS - MAX_LOGN);
+            (
+                to_montgomery(pow_mod(PSI, e)),
+                to_montgomery(pow_mod(psi_inv, e)),
+            )
+        })
+        .unzip()
+}
+
+fn save_ntt_twiddles(out_dir: &OsStr) {
+    let dest_path = Path::new(&out_dir).join("ntt_twiddles.rs");
+    let (twiddles, inv_twiddles) = ntt_twiddles();
+    // 1 / 2^logn in Montgomery form, indexed by logn
+    let inv_n: Vec<u16> = (0..=MAX_LOGN)
+        .map(|logn| to_montgomery(pow_mod(pow_mod(2, logn), Q as u32 - 2)))
+        .collect();
+
+    fs::write(
+        &dest_path,
+        format!(
+            "\
+static NTT_TWIDDLES: [u16; {}] = {twiddles:?};
+
+static INTT_TWIDDLES: [u16; {}] = {inv_twiddles:?};
+
+static INV_N_MONTGOMERY: [u16; {}] = {inv_n:?};",
+            twiddles.len(),
+            inv_twiddles.len(),
+            inv_n.len(),
+        ),
+    )
+    .unwrap();
+}
+
 fn main() {
     let out_dir = env::var_os("OUT_DIR").unwrap();
     save_fft_constants(&out_dir);
     save_ntt_constants(&out_dir);
+    save_ntt_twiddles(&out_dir);
     println!("cargo::rerun-if-changed=build.rs");
 }
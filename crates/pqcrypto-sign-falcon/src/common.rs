@@ -1,20 +1,23 @@
 #![allow(dead_code)]
 
-use std::hash::Hasher;
+use std::io;
 
 use crate::falcon::SALT_LEN;
+use crate::zq::Zq;
 
-use rs_shake256::{HasherContext, Shake256Hasher};
+use sha3::digest::{ExtendableOutput, Update, XofReader};
+use sha3::Shake256;
 
 pub const Q: u32 = 12 * 1024 + 1;
 
 type Coefficients<T> = Vec<T>;
 
-pub(crate) struct Polynomial<T: Copy> {
+#[derive(Clone)]
+pub(crate) struct Polynomial<T> {
     pub coefficients: Coefficients<T>,
 }
 
-impl<T: Copy> Polynomial<T> {
+impl<T> Polynomial<T> {
     fn new(coefficients: Coefficients<T>) -> Self {
         Self { coefficients }
     }
@@ -49,29 +52,132 @@ impl<T: Copy> Polynomial<T> {
         result.extend(iter_even);
         Self::new(result)
     }
+}
+
+impl Polynomial<Zq> {
+    pub fn hash_to_point(message: &[u8], salt: &[u8; SALT_LEN], n: usize) -> Self {
+        Self::hash_to_point_parts(&[message], salt, n)
+    }
+
+    /// hash_to_point whose timing does not depend on the message or the salt
+    pub fn hash_to_point_ct(message: &[u8], salt: &[u8; SALT_LEN], n: usize) -> Self {
+        let mut hasher = PointHasher::new(salt);
+        hasher.update(message);
+        hasher.finalize_ct(n)
+    }
+
+    /// Hashes the concatenation of the parts, without materializing it
+    pub fn hash_to_point_parts(parts: &[&[u8]], salt: &[u8; SALT_LEN], n: usize) -> Self {
+        let mut hasher = PointHasher::new(salt);
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize(n)
+    }
+}
+
+/// Extra 16-bit chunks squeezed by the constant-time hash_to_point, indexed by logn
+///
+/// Large enough that fewer than n of the chunks are accepted with negligible probability.
+const CT_OVERSAMPLING: [usize; 11] = [0, 65, 67, 71, 77, 86, 100, 122, 154, 205, 287];
+
+/// Data fed in chunks, from slices, readers or as an `io::Write`
+pub(crate) trait Absorb: io::Write + Sized {
+    fn update(&mut self, data: &[u8]);
+
+    /// Feeds the whole reader in, returning the number of bytes read
+    fn update_reader(&mut self, mut reader: impl io::Read) -> io::Result<u64> {
+        io::copy(&mut reader, self)
+    }
+}
+
+/// Incremental hash_to_point: absorbs salt || message, then squeezes the point
+pub(crate) struct PointHasher {
+    shake: Shake256,
+}
+
+impl PointHasher {
+    pub fn new(salt: &[u8; SALT_LEN]) -> Self {
+        let mut shake = Shake256::default();
+        shake.update(salt);
+        Self { shake }
+    }
 
-    pub fn hash_to_point(message: &[u8], salt: &[u8; SALT_LEN], n: usize) -> Polynomial<u32> {
-        const OUTPUT_SIZE: usize = 2;
+    pub fn update(&mut self, data: &[u8]) {
+        self.shake.update(data);
+    }
+
+    /// Rejection sampling of n coefficients from 16-bit chunks of the XOF stream
+    pub fn finalize(self, n: usize) -> Polynomial<Zq> {
         const K: u32 = (1u32 << 16) / Q;
-        let mut shake = Shake256Hasher::<OUTPUT_SIZE>::default();
-        shake.write(salt);
-        shake.write(message);
+        let mut xof = self.shake.finalize_xof();
 
         (0..n)
+            .map(|_| loop {
+                let mut chunk = [0u8; 2];
+                xof.read(&mut chunk);
+                let elt = u16::from_be_bytes(chunk) as u32;
+                if elt < K * Q {
+                    return Zq::new(elt);
+                }
+            })
+            .collect()
+    }
+
+    /// Same point as `finalize`, computed without data-dependent branches or indices
+    ///
+    /// A fixed number of chunks is squeezed and reduced, rejected chunks are marked
+    /// with 0xFFFF, and the accepted ones are then moved to the front by a network
+    /// of conditional swaps at distances 1, 2, 4, ...
+    pub fn finalize_ct(self, n: usize) -> Polynomial<Zq> {
+        const REJECTED: u32 = 0xFFFF;
+        let over = CT_OVERSAMPLING[n.ilog2() as usize];
+        let m = n + over;
+        let mut xof = self.shake.finalize_xof();
+        // x - m if x >= m, else x
+        let cond_sub =
+            |x: u32, m: u32| x.wrapping_sub(m & (x.wrapping_sub(m) >> 31).wrapping_sub(1));
+
+        let mut chunks: Vec<u32> = (0..m)
             .map(|_| {
-                loop {
-                    let byte_array_wrapper = <Shake256Hasher<OUTPUT_SIZE> as HasherContext<OUTPUT_SIZE>>::finish(&mut shake);
-                    let byte_array = byte_array_wrapper.as_ref();
-                    let elt = ((byte_array[0] as u32) << 8) | byte_array[1] as u32;
-                    if elt < K * Q {
-                        return elt % Q;
-                    }
+                let mut chunk = [0u8; 2];
+                xof.read(&mut chunk);
+                let w = u16::from_be_bytes(chunk) as u32;
+                // w mod q for w < 5q, by conditional subtractions of 2q, 2q and q
+                let wr = cond_sub(cond_sub(cond_sub(w, 2 * Q), 2 * Q), Q);
+                // Chunks of at least 5q are rejected
+                let rejected = (w.wrapping_sub(5 * Q) >> 31).wrapping_sub(1);
+                (wr | rejected) & REJECTED
+            })
+            .collect();
+
+        let mut p = 1;
+        while p <= over {
+            // Number of accepted chunks before the current one
+            let mut v = 0u32;
+            for u in 0..m {
+                let sv = chunks[u];
+                // Distance the chunk has to move towards the front
+                let j = (u as u32).wrapping_sub(v);
+                let mut mask = (sv >> 15).wrapping_sub(1);
+                v = v.wrapping_sub(mask);
+                if u < p {
+                    continue;
                 }
-            }).collect()
+                let dv = chunks[u - p];
+                mask &= (((j & p as u32) + 0x1FF) >> 9).wrapping_neg();
+                chunks[u] = sv ^ (mask & (sv ^ dv));
+                chunks[u - p] = dv ^ (mask & (sv ^ dv));
+            }
+            p <<= 1;
+        }
+
+        chunks.truncate(n);
+        chunks.into_iter().map(Zq::new).collect()
     }
 }
 
-impl<T: Copy> Default for Polynomial<T> {
+impl<T> Default for Polynomial<T> {
     fn default() -> Self {
         Self {
             coefficients: Vec::default(),
@@ -79,13 +185,13 @@ impl<T: Copy> Default for Polynomial<T> {
     }
 }
 
-impl<T: Copy> Extend<T> for Polynomial<T> {
+impl<T> Extend<T> for Polynomial<T> {
     fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
         self.coefficients.extend(iter);
     }
 }
 
-impl<T: Copy> FromIterator<T> for Polynomial<T> {
+impl<T> FromIterator<T> for Polynomial<T> {
     fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
         Self {
             coefficients: Vec::from_iter(iter),
@@ -93,3 +199,114 @@ impl<T: Copy> FromIterator<T> for Polynomial<T> {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(point: &Polynomial<Zq>) -> Vec<u32> {
+        point.coefficients.iter().map(|c| c.value()).collect()
+    }
+
+    // Generated with the hash_to_point of the reference falcon.py implementation
+    const SALT_RANGE: [u8; SALT_LEN] = {
+        let mut salt = [0u8; SALT_LEN];
+        let mut i = 0;
+        while i < SALT_LEN {
+            salt[i] = i as u8;
+            i += 1;
+        }
+        salt
+    };
+    const SALT_A5: [u8; SALT_LEN] = [0xA5; SALT_LEN];
+
+    #[test]
+    fn hash_to_point_kat() {
+        let vectors: [(&[u8], &[u8; SALT_LEN], [u32; 16]); 3] = [
+            (
+                b"",
+                &SALT_RANGE,
+                [
+                    6419, 5361, 8610, 3358, 11293, 385, 8882, 1723, 1038, 1958, 10976, 6076, 10631,
+                    982, 11916, 10912,
+                ],
+            ),
+            (
+                b"data1",
+                &SALT_RANGE,
+                [
+                    9138, 4527, 713, 7258, 8023, 11205, 6828, 4933, 10174, 11024, 4440, 4054,
+                    11156, 750, 257, 270,
+                ],
+            ),
+            (
+                b"The quick brown fox jumps over the lazy dog",
+                &SALT_A5,
+                [
+                    9574, 1954, 11038, 9791, 3649, 5583, 7412, 8029, 2274, 7293, 8507, 4929, 2564,
+                    12254, 5394, 8572,
+                ],
+            ),
+        ];
+
+        for (message, salt, expected) in vectors {
+            let point = Polynomial::<Zq>::hash_to_point(message, salt, expected.len());
+            assert_eq!(values(&point), expected);
+        }
+    }
+
+    /// First and last coefficients and sum of a full-length point of "data1"
+    struct FullLengthVector {
+        salt: [u8; SALT_LEN],
+        n: usize,
+        head: [u32; 8],
+        tail: [u32; 8],
+        sum: u32,
+    }
+
+    #[test]
+    fn hash_to_point_kat_full_length() {
+        let vectors = [
+            FullLengthVector {
+                salt: SALT_RANGE,
+                n: 512,
+                head: [9138, 4527, 713, 7258, 8023, 11205, 6828, 4933],
+                tail: [6932, 3825, 1419, 10888, 7131, 6220, 5594, 388],
+                sum: 3212466,
+            },
+            FullLengthVector {
+                salt: SALT_A5,
+                n: 1024,
+                head: [10752, 2265, 595, 9252, 7636, 8381, 2598, 6284],
+                tail: [6040, 8658, 10923, 77, 5871, 4371, 3394, 10190],
+                sum: 6226486,
+            },
+        ];
+
+        for vector in vectors {
+            let n = vector.n;
+            let point = values(&Polynomial::<Zq>::hash_to_point(b"data1", &vector.salt, n));
+            assert_eq!(point[..8], vector.head);
+            assert_eq!(point[n - 8..], vector.tail);
+            assert_eq!(point.iter().sum::<u32>(), vector.sum);
+        }
+    }
+
+    #[test]
+    fn hash_to_point_ct_matches_variable_time() {
+        for (n, salt) in [(512, SALT_RANGE), (1024, SALT_A5)] {
+            for message in [b"".as_slice(), b"data1", b"The quick brown fox"] {
+                let point = Polynomial::<Zq>::hash_to_point(message, &salt, n);
+                let point_ct = Polynomial::<Zq>::hash_to_point_ct(message, &salt, n);
+                assert_eq!(values(&point), values(&point_ct));
+            }
+        }
+    }
+
+    #[test]
+    fn hash_to_point_parts_matches_concatenation() {
+        let whole = Polynomial::<Zq>::hash_to_point(b"hello world", &SALT_A5, 512);
+        let parts =
+            Polynomial::<Zq>::hash_to_point_parts(&[b"hel", b"", b"lo wor", b"ld"], &SALT_A5, 512);
+        assert_eq!(values(&whole), values(&parts));
+    }
+}
@@ -1,5 +1,10 @@
+#![allow(dead_code)]
+
 use bit_vec::BitVec;
 
+/// Largest absolute value of a compressed coefficient
+const MAX_COEFFICIENT: i16 = 2047;
+
 pub trait Compression {
     fn compress(v: &[i16], slen: usize) -> Option<Vec<u8>>;
 }
@@ -8,9 +13,77 @@ pub trait Decompression {
     fn decompress(x: &[u8], slen: usize, n: usize) -> Option<Vec<i16>>;
 }
 
-struct NaiveCompression;
+/// Packs the lowest `bits` bits of each value, most significant bit first,
+/// with shifts and masks only, as the reference trim_i16_encode does
+pub fn pack_bits(values: &[i16], bits: usize) -> Vec<u8> {
+    let mask = (1u32 << bits) - 1;
+    let mut out = Vec::with_capacity((values.len() * bits).div_ceil(8));
+    let mut acc = 0u32;
+    let mut acc_len = 0;
+    for &value in values {
+        acc = (acc << bits) | (value as u32 & mask);
+        acc_len += bits;
+        while acc_len >= 8 {
+            acc_len -= 8;
+            out.push((acc >> acc_len) as u8);
+        }
+    }
+    if acc_len > 0 {
+        out.push((acc << (8 - acc_len)) as u8);
+    }
+    out
+}
+
+/// Unpacks n values of `bits` bits each, rejecting wrong lengths and non-zero padding
+pub fn unpack_bits(x: &[u8], bits: usize, n: usize) -> Option<Vec<u16>> {
+    if x.len() != (n * bits).div_ceil(8) {
+        return None;
+    }
+
+    let mask = (1u32 << bits) - 1;
+    let mut bytes = x.iter();
+    let mut acc = 0u32;
+    let mut acc_len = 0;
+    let mut v = Vec::with_capacity(n);
+    for _ in 0..n {
+        while acc_len < bits {
+            acc = (acc << 8) | *bytes.next()? as u32;
+            acc_len += 8;
+        }
+        acc_len -= bits;
+        v.push(((acc >> acc_len) & mask) as u16);
+    }
+    if acc & ((1u32 << acc_len) - 1) != 0 {
+        return None;
+    }
+    Some(v)
+}
+
+/// Unpacks n two's complement values of `bits` bits each, rejecting -2^(bits - 1)
+pub fn unpack_signed_bits(x: &[u8], bits: usize, n: usize) -> Option<Vec<i16>> {
+    let shift = 16 - bits;
+    let sign = 1u16 << (bits - 1);
+    let v = unpack_bits(x, bits, n)?;
+    let forbidden = v.iter().fold(false, |acc, &value| acc | (value == sign));
+    if forbidden {
+        return None;
+    }
+    Some(
+        v.into_iter()
+            .map(|value| ((value << shift) as i16) >> shift)
+            .collect(),
+    )
+}
+
+pub struct NaiveCompression;
 impl Compression for NaiveCompression {
     fn compress(v: &[i16], slen: usize) -> Option<Vec<u8>> {
+        if v.iter()
+            .any(|coef| coef.unsigned_abs() > MAX_COEFFICIENT as u16)
+        {
+            return None;
+        }
+
         let mut u = v
             .iter()
             .copied()
@@ -31,14 +104,15 @@ impl Compression for NaiveCompression {
             return None;
         }
 
-        u.extend(std::iter::repeat_n(false, 8 * slen));
+        u.extend(std::iter::repeat_n(false, 8 * slen - u.len()));
 
         Some(u.to_bytes())
     }
 }
 
-struct NaiveDecompression;
+pub struct NaiveDecompression;
 impl Decompression for NaiveDecompression {
+    /// Inverse of `NaiveCompression`, rejecting every other encoding
     fn decompress(x: &[u8], slen: usize, n: usize) -> Option<Vec<i16>> {
         if x.len() > slen {
             return None;
@@ -46,30 +120,198 @@ impl Decompression for NaiveDecompression {
         let u = BitVec::from_bytes(x);
 
         let mut index = 0;
-        let mut v = Vec::with_capacity(slen);
+        let mut v = Vec::with_capacity(n);
 
         for _ in 0..n {
-            if index + 8 >= u.len() {
+            if index + 8 > u.len() {
                 return None;
             };
 
-            let sign = if u[index] { -1 } else { 0 };
-            let low_bits = ((index + 1)..(index + 8))
-                .rev()
-                .enumerate()
-                .map(|(i, index)| (v[index] as i16) << i)
-                .reduce(std::ops::BitOr::bitor)
-                .unwrap();
-            let high_bits = ((index + 8)..).take_while(|i| u[*i]).count() as i16;
-
-            v.push(sign * ((high_bits << 7) | low_bits));
+            let sign = if u[index] { -1 } else { 1 };
+            let low_bits =
+                ((index + 1)..(index + 8)).fold(0i16, |bits, index| (bits << 1) | u[index] as i16);
             index += 8;
+
+            let mut high_bits = 0i16;
+            loop {
+                match u.get(index) {
+                    None => return None,
+                    Some(true) => break,
+                    Some(false) => high_bits += 1,
+                }
+                if (high_bits << 7) > MAX_COEFFICIENT {
+                    return None;
+                }
+                index += 1;
+            }
+            index += 1;
+
+            let s = (high_bits << 7) | low_bits;
+            // Negative zero has no canonical encoding
+            if s == 0 && sign < 0 {
+                return None;
+            }
+            v.push(sign * s);
         }
 
-        if v.len() != n {
+        if u.iter().skip(index).any(|bit| bit) {
             return None;
         }
 
         Some(v)
     }
 }
+
+/// Bit width of each coefficient in the constant-time format
+const CONSTANT_TIME_BITS: usize = 12;
+
+/// Fixed-width encoding, whose timing does not depend on the coefficients
+pub struct ConstantTimeCompression;
+impl Compression for ConstantTimeCompression {
+    fn compress(v: &[i16], slen: usize) -> Option<Vec<u8>> {
+        if (v.len() * CONSTANT_TIME_BITS).div_ceil(8) != slen {
+            return None;
+        }
+        let out_of_range = v.iter().fold(false, |acc, coef| {
+            acc | (coef.unsigned_abs() > MAX_COEFFICIENT as u16)
+        });
+        if out_of_range {
+            return None;
+        }
+
+        Some(pack_bits(v, CONSTANT_TIME_BITS))
+    }
+}
+
+impl Decompression for ConstantTimeCompression {
+    fn decompress(x: &[u8], slen: usize, n: usize) -> Option<Vec<i16>> {
+        if x.len() != slen {
+            return None;
+        }
+        unpack_signed_bits(x, CONSTANT_TIME_BITS, n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    /// Bytes of a string of '0' and '1' (spaces are skipped), zero-padded to
+    /// `len` bytes
+    fn from_bits(bits: &str, len: usize) -> Vec<u8> {
+        let mut u: BitVec = bits
+            .chars()
+            .filter(|&c| c != ' ')
+            .map(|c| c == '1')
+            .collect();
+        u.extend(std::iter::repeat_n(false, 8 * len - u.len()));
+        u.to_bytes()
+    }
+
+    fn decompress(bits: &str, len: usize, n: usize) -> Option<Vec<i16>> {
+        NaiveDecompression::decompress(&from_bits(bits, len), len, n)
+    }
+
+    #[test]
+    fn naive_compression_round_trip() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let v: Vec<i16> = (0..512).map(|_| rng.random_range(-300..=300)).collect();
+            let x = NaiveCompression::compress(&v, 1000).unwrap();
+            assert_eq!(NaiveDecompression::decompress(&x, 1000, 512), Some(v));
+        }
+        let extremes = [0, 1, -1, 127, -128, 2047, -2047];
+        let x = NaiveCompression::compress(&extremes, 20).unwrap();
+        assert_eq!(
+            NaiveDecompression::decompress(&x, 20, extremes.len()),
+            Some(extremes.to_vec())
+        );
+    }
+
+    #[test]
+    fn naive_decompression_rejects_non_canonical_encodings() {
+        // Sign, 7 low bits, high bits in unary, terminated by a one
+        assert_eq!(decompress("0 0000000 1", 2, 1), Some(vec![0]));
+        assert_eq!(decompress("1 0000001 00 1", 2, 1), Some(vec![-257]));
+
+        // Negative zero
+        assert_eq!(decompress("1 0000000 1", 2, 1), None);
+        // Non-zero padding
+        assert_eq!(decompress("0 0000000 1 0001", 2, 1), None);
+        // Truncated: the second coefficient is missing, or unterminated
+        assert_eq!(decompress("0 0000000 1", 2, 2), None);
+        assert_eq!(decompress("0 0000000 1 0 0000000 0000000", 3, 2), None);
+        // |s| = 2048 is out of range, 2047 is the largest value
+        let zeros = "0".repeat(16);
+        assert_eq!(decompress(&format!("0 0000000 {zeros} 1"), 4, 1), None);
+        let zeros = "0".repeat(15);
+        assert_eq!(
+            decompress(&format!("0 1111111 {zeros} 1"), 4, 1),
+            Some(vec![2047])
+        );
+        // Longer than slen
+        assert_eq!(NaiveDecompression::decompress(&[0, 0x80, 0], 2, 1), None);
+    }
+
+    #[test]
+    fn naive_compression_rejects_large_values() {
+        assert_eq!(NaiveCompression::compress(&[2048], 100), None);
+        assert_eq!(NaiveCompression::compress(&[-2048], 100), None);
+        assert_eq!(NaiveCompression::compress(&[0; 10], 9), None);
+    }
+
+    #[test]
+    fn constant_time_compression_round_trip() {
+        let v = [2047, -2047, 0, 1, -1];
+        let x = ConstantTimeCompression::compress(&v, 8).unwrap();
+        assert_eq!(
+            ConstantTimeCompression::decompress(&x, 8, v.len()),
+            Some(v.to_vec())
+        );
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let v: Vec<i16> = (0..512).map(|_| rng.random_range(-2047..=2047)).collect();
+        let x = ConstantTimeCompression::compress(&v, 768).unwrap();
+        assert_eq!(ConstantTimeCompression::decompress(&x, 768, 512), Some(v));
+    }
+
+    #[test]
+    fn constant_time_encoding_is_twelve_bit_twos_complement() {
+        let x = ConstantTimeCompression::compress(&[2047, -2047], 3).unwrap();
+        assert_eq!(x, [0x7f, 0xf8, 0x01]);
+        let x = ConstantTimeCompression::compress(&[-1], 2).unwrap();
+        assert_eq!(x, [0xff, 0xf0]);
+    }
+
+    #[test]
+    fn constant_time_decompression_rejects_invalid_encodings() {
+        // 0x800 is -2048, which is out of range
+        assert_eq!(
+            ConstantTimeCompression::decompress(&[0x80, 0x00], 2, 1),
+            None
+        );
+        assert_eq!(
+            ConstantTimeCompression::decompress(&[0x00, 0x08, 0x00], 3, 2),
+            None
+        );
+        // One byte short or long
+        assert_eq!(ConstantTimeCompression::decompress(&[0x00; 2], 3, 2), None);
+        assert_eq!(ConstantTimeCompression::decompress(&[0x00; 4], 3, 2), None);
+        assert_eq!(ConstantTimeCompression::decompress(&[0x00; 4], 4, 2), None);
+        // Non-zero padding
+        assert_eq!(
+            ConstantTimeCompression::decompress(&[0x00, 0x01], 2, 1),
+            None
+        );
+    }
+
+    #[test]
+    fn constant_time_compression_rejects_large_values() {
+        assert_eq!(ConstantTimeCompression::compress(&[2048], 2), None);
+        assert_eq!(ConstantTimeCompression::compress(&[-2048], 2), None);
+        assert_eq!(ConstantTimeCompression::compress(&[0; 2], 2), None);
+    }
+}
@@ -1,16 +1,36 @@
+#![allow(dead_code)]
+
+use std::io;
 use std::marker::PhantomData;
 
 use num_complex::{Complex, Complex64};
 use num_traits::identities::Zero;
-use rand::{rng, CryptoRng};
+use rand::rngs::ThreadRng;
+use rand::{rng, CryptoRng, Rng};
 
-use crate::encoding::{Compression, Decompression};
-use crate::common::Polynomial;
+use crate::encoding::{
+    pack_bits, unpack_bits, unpack_signed_bits, Compression, ConstantTimeCompression,
+    Decompression, NaiveCompression, NaiveDecompression,
+};
+use crate::common::{Absorb, PointHasher, Polynomial, Q};
+use crate::fft::FFT;
 use crate::ntt::NTT;
+use crate::ntrugen::ntru_gen;
+use crate::prehash::PreHash;
+use crate::prng::{ChaCha20Prng, Shake256Rng};
+use crate::samplerz::sampler_z_ct;
+use crate::zq::Zq;
 
 pub const HEAD_LEN: usize = 1;
 pub const SALT_LEN: usize = 40;
 pub const SEED_LEN: usize = 32;
+pub const PUBLIC_KEY_BITS: usize = 14;
+pub const BIG_FG_BITS: usize = 8;
+pub const CONTEXT_MAX_LEN: usize = 255;
+
+/// Domain separators of pure and pre-hash FN-DSA messages
+const FN_DSA_PURE: u8 = 0x00;
+const FN_DSA_PREHASH: u8 = 0x01;
 
 type Seed = [u8; SEED_LEN];
 
@@ -20,6 +40,10 @@ struct PublicParameters {
     sigmin: f64,
     sig_bound: i64,
     sig_bytelen: usize,
+    sig_ct_bytelen: usize,
+    /// Longest variable-length signature, `FALCON_SIG_COMPRESSED_MAXSIZE`
+    sig_max_bytelen: usize,
+    fg_bits: usize,
 }
 
 trait Falcon {
@@ -34,6 +58,9 @@ impl Falcon for Falcon512 {
         sigmin: 1.2778336969128337,
         sig_bound: 34034726,
         sig_bytelen: 666,
+        sig_ct_bytelen: 809,
+        sig_max_bytelen: 752,
+        fg_bits: 6,
     };
 }
 
@@ -45,15 +72,120 @@ impl Falcon for Falcon1024 {
         sigmin: 1.298280334344292,
         sig_bound: 70265242,
         sig_bytelen: 1280,
+        sig_ct_bytelen: 1577,
+        sig_max_bytelen: 1462,
+        fg_bits: 5,
     };
 }
 
+/// FN-DSA (FIPS 206) shares the parameter sets of Falcon
+type FnDsa512 = Falcon512;
+type FnDsa1024 = Falcon1024;
+
+/// Prefix of the FN-DSA message representative, domain || len(ctx) || ctx
+fn fn_dsa_prefix(domain: u8, context: &[u8]) -> Result<Vec<u8>, String> {
+    if context.len() > CONTEXT_MAX_LEN {
+        return Err("Context string is longer than 255 bytes".into());
+    }
+    let mut prefix = vec![domain, context.len() as u8];
+    prefix.extend(context);
+    Ok(prefix)
+}
+
+/// Prefix of the HashFN-DSA message representative, followed by the digest itself
+fn prehash_prefix(hash: PreHash, digest: &[u8], context: &[u8]) -> Result<Vec<u8>, String> {
+    if digest.len() != hash.digest_len() {
+        return Err("Digest length does not match the hash function".into());
+    }
+    let mut prefix = fn_dsa_prefix(FN_DSA_PREHASH, context)?;
+    prefix.extend(hash.oid());
+    Ok(prefix)
+}
+
+/// Parameters of the set with the given logn, if any
+fn parameters(logn: u8) -> Option<PublicParameters> {
+    match logn {
+        9 => Some(Falcon512::PARAMETERS),
+        10 => Some(Falcon1024::PARAMETERS),
+        _ => None,
+    }
+}
+
 pub(crate) enum LdlTree {
     Branch(Polynomial<Complex64>, [Box<LdlTree>; 2]),
     Leaf([Complex64; 2]),
 }
 
 impl LdlTree {
+    /// Falcon tree of the lattice spanned by the rows of the basis
+    fn from_basis(basis: &Basis) -> Self {
+        // Entry of the Gram matrix: <x, y> = x0 * adj(y0) + x1 * adj(y1)
+        let gram = |x: &[Polynomial<Complex64>; 2], y: &[Polynomial<Complex64>; 2]| {
+            x[0].clone()
+                .mul_fft(y[0].clone().adj_fft())
+                .add_fft(x[1].clone().mul_fft(y[1].clone().adj_fft()))
+        };
+        let [b0, b1] = basis;
+        Self::ffldl(gram(b0, b0), gram(b0, b1), gram(b1, b1))
+    }
+
+    /// ffLDL* decomposition of the self-adjoint matrix [[g00, g01], [adj(g01), g11]]
+    fn ffldl(
+        g00: Polynomial<Complex64>,
+        g01: Polynomial<Complex64>,
+        g11: Polynomial<Complex64>,
+    ) -> Self {
+        let n = g00.len();
+        let l10 = g01.adj_fft().div_fft(g00.clone());
+        let d00 = g00;
+        let d11 = g11.sub_fft(
+            l10.clone()
+                .mul_fft(l10.clone().adj_fft())
+                .mul_fft(d00.clone()),
+        );
+
+        let children = if n > 2 {
+            [d00, d11].map(|d| {
+                let (d0, d1) = d.split_fft();
+                Box::new(Self::ffldl(d0.clone(), d1, d0))
+            })
+        } else {
+            [d00, d11].map(|d| Box::new(LdlTree::Leaf([d.coefficients[0], Complex64::zero()])))
+        };
+        LdlTree::Branch(l10, children)
+    }
+
+    /// Samples z close to the target t in the lattice whose Falcon tree is self
+    fn ffsampling<R>(
+        &self,
+        t: [Polynomial<Complex64>; 2],
+        sigmin: f64,
+        rng: &mut R,
+    ) -> [Polynomial<Complex64>; 2]
+    where
+        R: Rng + CryptoRng,
+    {
+        let [t0, t1] = t;
+        match self {
+            LdlTree::Branch(l10, [tree0, tree1]) => {
+                let (t10, t11) = t1.clone().split_fft();
+                let [z10, z11] = tree1.ffsampling([t10, t11], sigmin, rng);
+                let z1 = z10.merge_fft(z11);
+
+                let t0b = t0.add_fft(t1.sub_fft(z1.clone()).mul_fft(l10.clone()));
+                let (t0b0, t0b1) = t0b.split_fft();
+                let [z00, z01] = tree0.ffsampling([t0b0, t0b1], sigmin, rng);
+                let z0 = z00.merge_fft(z01);
+
+                [z0, z1]
+            }
+            LdlTree::Leaf([sigma, _]) => [t0, t1].map(|t| {
+                let z = sampler_z_ct(t.coefficients[0].re, sigma.re, sigmin, rng);
+                std::iter::once(Complex64::new(z as f64, 0.)).collect()
+            }),
+        }
+    }
+
     fn normalize_inplace(&mut self, sigma: f64) {
         match self {
             LdlTree::Branch(_, children) => {
@@ -72,29 +204,256 @@ impl LdlTree {
     }
 }
 
-type Polynomials = [Polynomial<i16>; 4];
+pub(crate) type Polynomials = [Polynomial<i16>; 4];
+type Basis = [[Polynomial<Complex64>; 2]; 2];
 struct SecretKey<F: Falcon> {
     polys: Polynomials,
+    b0_fft: Basis,
+    tree: LdlTree,
     _marker: PhantomData<F>,
 }
 
 impl<F: Falcon> SecretKey<F> {
     fn new(polys: Polynomials) -> Self {
-        todo!()
+        let [f, g, big_f, big_g] = &polys;
+        let fft = |p: &Polynomial<i16>| Polynomial::<Complex64>::from(p).fft();
+        let b0_fft = [[fft(g), fft(f).neg()], [fft(big_g), fft(big_f).neg()]];
+        let tree = LdlTree::from_basis(&b0_fft).normalize(F::PARAMETERS.sigma);
+        Self {
+            polys,
+            b0_fft,
+            tree,
+            _marker: PhantomData,
+        }
     }
 
-    fn sign(&self, message: &[u8]) -> Signature {
-        self.sign_rng(message, rng())
+    /// Whether f, g, F and G fit the widths of the encoded format
+    fn is_encodable(polys: &Polynomials) -> bool {
+        let [f, g, big_f, big_g] = polys;
+        let fits = |p: &Polynomial<i16>, bits: usize| {
+            let limit = (1 << (bits - 1)) - 1;
+            p.coefficients.iter().all(|c| c.abs() <= limit)
+        };
+        let fg_bits = F::PARAMETERS.fg_bits;
+        fits(f, fg_bits) && fits(g, fg_bits) && fits(big_f, BIG_FG_BITS) && fits(big_g, BIG_FG_BITS)
     }
 
-    fn sign_rng(&self, message: &[u8], rng: impl CryptoRng) -> Signature {
-        todo!()
+    /// Header 0x50 + logn, followed by f, g and F; G is recomputed on decoding
+    fn to_bytes(&self) -> Vec<u8> {
+        let params = F::PARAMETERS;
+        let [f, g, big_f, _] = &self.polys;
+        debug_assert!(Self::is_encodable(&self.polys));
+        let mut bytes = vec![0x50 + params.n.ilog2() as u8];
+        bytes.extend(pack_bits(&f.coefficients, params.fg_bits));
+        bytes.extend(pack_bits(&g.coefficients, params.fg_bits));
+        bytes.extend(pack_bits(&big_f.coefficients, BIG_FG_BITS));
+        bytes
+    }
+
+    fn sign(&self, message: &[u8], format: SignatureFormat) -> Signature {
+        self.sign_rng(message, format, rng())
+    }
+
+    /// Panics on `SignatureFormat::FnDsa`, which only `sign_fn_dsa` and
+    /// `sign_prehashed` produce
+    fn sign_rng(&self, message: &[u8], format: SignatureFormat, rng: impl CryptoRng) -> Signature {
+        assert_ne!(format, SignatureFormat::FnDsa, "FN-DSA needs a context");
+        self.sign_parts_rng(&[message], format, rng)
+    }
+
+    /// FN-DSA signature of the message under a context string of at most 255 bytes
+    fn sign_fn_dsa(&self, message: &[u8], context: &[u8]) -> Result<Signature, String> {
+        self.sign_fn_dsa_rng(message, context, rng())
+    }
+
+    fn sign_fn_dsa_rng(
+        &self,
+        message: &[u8],
+        context: &[u8],
+        rng: impl CryptoRng,
+    ) -> Result<Signature, String> {
+        let prefix = fn_dsa_prefix(FN_DSA_PURE, context)?;
+        Ok(self.sign_parts_rng(&[&prefix, message], SignatureFormat::FnDsa, rng))
+    }
+
+    /// HashFN-DSA signature of a digest computed with the given hash function
+    fn sign_prehashed(
+        &self,
+        digest: &[u8],
+        hash: PreHash,
+        context: &[u8],
+    ) -> Result<Signature, String> {
+        self.sign_prehashed_rng(digest, hash, context, rng())
+    }
+
+    fn sign_prehashed_rng(
+        &self,
+        digest: &[u8],
+        hash: PreHash,
+        context: &[u8],
+        rng: impl CryptoRng,
+    ) -> Result<Signature, String> {
+        let prefix = prehash_prefix(hash, digest, context)?;
+        Ok(self.sign_parts_rng(&[&prefix, digest], SignatureFormat::FnDsa, rng))
+    }
+
+    /// Signature of the concatenation of the parts
+    fn sign_parts_rng(
+        &self,
+        parts: &[&[u8]],
+        format: SignatureFormat,
+        mut rng: impl CryptoRng,
+    ) -> Signature {
+        let mut salt = [0u8; SALT_LEN];
+        rng.fill(&mut salt);
+        let hashed = Polynomial::<Zq>::hash_to_point_parts(parts, &salt, F::PARAMETERS.n);
+        self.sign_hashed(salt, &hashed, format, &mut rng)
+    }
+
+    /// Signature of a message already hashed to a point with the salt
+    ///
+    /// As in the reference implementation, every attempt seeds a fresh
+    /// `ChaCha20Prng` with 56 bytes of `rng`, which SamplerZ then draws from.
+    ///
+    /// Seeded signatures are still not bit-identical to those of the C code,
+    /// and are not meant to be: `sampler_z_ct` reads all 8 bytes of each BerExp
+    /// comparison where C stops at the first differing byte, and `approx_exp`
+    /// differs from `fpr_expm_p63` in the lowest bit.
+    fn sign_hashed<R>(
+        &self,
+        salt: [u8; SALT_LEN],
+        hashed: &Polynomial<Zq>,
+        format: SignatureFormat,
+        rng: &mut R,
+    ) -> Signature
+    where
+        R: Rng + CryptoRng,
+    {
+        let params = F::PARAMETERS;
+        let head = format.head(params.n);
+
+        loop {
+            let mut prng = ChaCha20Prng::from_rng(rng);
+            let [s0, s1] = self.sample_preimage(hashed, &mut prng);
+            let norm_sign: i64 = s0
+                .coefficients
+                .iter()
+                .chain(&s1.coefficients)
+                .map(|&c| (c as i64).pow(2))
+                .sum();
+            if norm_sign > params.sig_bound {
+                continue;
+            }
+
+            let slen = format.content_len(&params);
+            if let Some(content) = format.compress(&s1.coefficients, slen) {
+                return Signature {
+                    head,
+                    salt,
+                    content,
+                };
+            }
+        }
+    }
+
+    /// Short (s0, s1) such that s0 + s1 * h = point mod q
+    fn sample_preimage<R>(&self, point: &Polynomial<Zq>, rng: &mut R) -> [Polynomial<i16>; 2]
+    where
+        R: Rng + CryptoRng,
+    {
+        let [[a, b], [c, d]] = &self.b0_fft;
+        let point_fft = Polynomial::<Complex64>::from(point).fft();
+        let point_fft_q: Polynomial<Complex64> = point_fft
+            .coefficients
+            .into_iter()
+            .map(|x| x / Q as f64)
+            .collect();
+        let t0_fft = point_fft_q.clone().mul_fft(d.clone());
+        let t1_fft = point_fft_q.neg().mul_fft(b.clone());
+
+        let sigmin = F::PARAMETERS.sigmin;
+        let [z0_fft, z1_fft] = self.tree.ffsampling([t0_fft, t1_fft], sigmin, rng);
+
+        let v0 = z0_fft
+            .clone()
+            .mul_fft(a.clone())
+            .add_fft(z1_fft.clone().mul_fft(c.clone()))
+            .ifft();
+        let v1 = z0_fft
+            .mul_fft(b.clone())
+            .add_fft(z1_fft.mul_fft(d.clone()))
+            .ifft();
+
+        let s0 = point
+            .coefficients
+            .iter()
+            .zip(v0.coefficients)
+            .map(|(&p, v)| (f64::from(p) - v.re.round()) as i16)
+            .collect();
+        let s1 = v1
+            .coefficients
+            .iter()
+            .map(|v| -v.re.round() as i16)
+            .collect();
+        [s0, s1]
     }
 }
 
 impl<F: Falcon> From<Seed> for SecretKey<F>{
+    /// The seed is expanded with SHAKE256, which unlike `StdRng` is specified
+    /// and keeps the key of a seed stable across dependency versions
     fn from(value: Seed) -> Self {
-        todo!()
+        let mut rng = Shake256Rng::from_seed(&value);
+        loop {
+            let polys = ntru_gen(F::PARAMETERS.n, &mut rng);
+            if Self::is_encodable(&polys) {
+                return Self::new(polys);
+            }
+        }
+    }
+}
+
+impl<F: Falcon> TryFrom<&[u8]> for SecretKey<F> {
+    type Error = String;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let params = F::PARAMETERS;
+        let n = params.n;
+        let [head, content @ ..] = value else {
+            return Err("Could not parse head".into());
+        };
+        if *head != 0x50 + n.ilog2() as u8 {
+            return Err("Invalid secret key header".into());
+        }
+        let fg_len = n * params.fg_bits / 8;
+        if content.len() != 2 * fg_len + n * BIG_FG_BITS / 8 {
+            return Err("Invalid secret key length".into());
+        }
+        let (f, rest) = content.split_at(fg_len);
+        let (g, big_f) = rest.split_at(fg_len);
+        let (Some(f), Some(g), Some(big_f)) = (
+            unpack_signed_bits(f, params.fg_bits, n),
+            unpack_signed_bits(g, params.fg_bits, n),
+            unpack_signed_bits(big_f, BIG_FG_BITS, n),
+        ) else {
+            return Err("Secret key coefficient out of range".into());
+        };
+        let f: Polynomial<i16> = f.into_iter().collect();
+        let g: Polynomial<i16> = g.into_iter().collect();
+        let big_f: Polynomial<i16> = big_f.into_iter().collect();
+
+        // fG - gF = q, so G = gF / f mod q
+        let g_big_f = Polynomial::<Zq>::from(&g).mul(Polynomial::from(&big_f));
+        let Some(big_g) = g_big_f.div(Polynomial::from(&f)) else {
+            return Err("f is not invertible mod q".into());
+        };
+        let limit = (1 << (BIG_FG_BITS - 1)) - 1;
+        let big_g: Polynomial<i16> = big_g.coefficients.into_iter().map(Zq::centered).collect();
+        if big_g.coefficients.iter().any(|c| c.abs() > limit) {
+            return Err("G coefficient out of range".into());
+        }
+
+        Ok(Self::new([f, g, big_f, big_g]))
     }
 }
 
@@ -104,22 +463,210 @@ struct PublicKey<F: Falcon> {
 }
 
 impl<F: Falcon> PublicKey<F> {
-    fn verify<D: Decompression>(&self, message: &[u8], signature: Signature) -> bool {
+    fn verify(&self, message: &[u8], signature: Signature) -> bool {
+        if signature.format() == SignatureFormat::FnDsa {
+            return false;
+        }
+        self.verify_parts(&[message], signature)
+    }
+
+    /// Verifies an FN-DSA signature of the message under the context string
+    fn verify_fn_dsa(&self, message: &[u8], context: &[u8], signature: Signature) -> bool {
+        if signature.format() != SignatureFormat::FnDsa {
+            return false;
+        }
+        let Ok(prefix) = fn_dsa_prefix(FN_DSA_PURE, context) else {
+            return false;
+        };
+        self.verify_parts(&[&prefix, message], signature)
+    }
+
+    /// Verifies a HashFN-DSA signature of a digest computed with the given hash function
+    fn verify_prehashed(
+        &self,
+        digest: &[u8],
+        hash: PreHash,
+        context: &[u8],
+        signature: Signature,
+    ) -> bool {
+        if signature.format() != SignatureFormat::FnDsa {
+            return false;
+        }
+        let Ok(prefix) = prehash_prefix(hash, digest, context) else {
+            return false;
+        };
+        self.verify_parts(&[&prefix, digest], signature)
+    }
+
+    /// Verifies a signature of the concatenation of the parts
+    fn verify_parts(&self, parts: &[&[u8]], signature: Signature) -> bool {
+        let hashed = Polynomial::<Zq>::hash_to_point_parts(parts, &signature.salt, F::PARAMETERS.n);
+        self.verify_hashed(hashed, &signature)
+    }
+
+    /// Verifies a signature of a message already hashed to a point with its salt
+    fn verify_hashed(&self, hashed: Polynomial<Zq>, signature: &Signature) -> bool {
         let params = F::PARAMETERS;
-        let Some(s1) = D::decompress(&signature.content, signature.content.len(), params.n) else {
+        let format = signature.format();
+        if signature.head != format.head(params.n) {
+            return false;
+        }
+        let slen = format.content_len(&params);
+        if !format.accepts_len(signature.content.len(), slen) {
             return false;
+        }
+        let Some(s1) = format.decompress(&signature.content, slen, params.n) else {
+            return false;
+        };
+        let s1: Polynomial<i16> = s1.into_iter().collect();
+
+        let s1_zq: Polynomial<Zq> = Polynomial::from(&s1);
+        let s0 = hashed.sub(s1_zq.mul(Polynomial::from(&self.h)));
+
+        let norm_sign: i64 = s0
+            .coefficients
+            .iter()
+            .map(|c| c.centered() as i64)
+            .chain(s1.coefficients.iter().map(|&c| c as i64))
+            .map(|c| c * c)
+            .sum();
+
+        norm_sign <= params.sig_bound
+    }
+
+    /// Header 0x00 + logn, followed by the coefficients of h at 14 bits each
+    fn to_bytes(&self) -> Vec<u8> {
+        let head = F::PARAMETERS.n.ilog2() as u8;
+        let mut bytes = vec![head];
+        bytes.extend(pack_bits(&self.h.coefficients, PUBLIC_KEY_BITS));
+        bytes
+    }
+}
+
+impl<F: Falcon> TryFrom<&[u8]> for PublicKey<F> {
+    type Error = String;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let n = F::PARAMETERS.n;
+        let [head, content @ ..] = value else {
+            return Err("Could not parse head".into());
+        };
+        if *head != n.ilog2() as u8 {
+            return Err("Invalid public key header".into());
+        }
+        let Some(h) = unpack_bits(content, PUBLIC_KEY_BITS, n) else {
+            return Err("Invalid public key length".into());
         };
+        if h.iter().any(|&c| c as u32 >= Q) {
+            return Err("Public key coefficient out of range".into());
+        }
 
-        let hashed: u32 = Polynomial::hash_to_point(message, &signature.salt, params.n);
-        let norm_sign: i64 = todo!();
+        Ok(PublicKey {
+            h: h.into_iter().map(|c| c as i16).collect(),
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<F: Falcon> TryFrom<&SecretKey<F>> for PublicKey<F>{
+    type Error = String;
 
-        norm_sign < params.sig_bound
+    /// h = g / f mod q, with coefficients in [0, q)
+    fn try_from(value: &SecretKey<F>) -> Result<Self, Self::Error> {
+        let [f, g, _, _] = &value.polys;
+        let f_zq: Polynomial<Zq> = Polynomial::from(f);
+        let Some(h) = Polynomial::<Zq>::from(g).div(f_zq) else {
+            return Err("f is not invertible mod q".into());
+        };
+
+        Ok(PublicKey {
+            h: h.coefficients
+                .into_iter()
+                .map(|c| c.value() as i16)
+                .collect(),
+            _marker: PhantomData,
+        })
     }
 }
 
-impl<F: Falcon> From<&SecretKey<F>> for PublicKey<F>{
-    fn from(value: &SecretKey<F>) -> Self {
-        todo!()
+/// Encodings of s1, identified by the high nibble of the signature header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SignatureFormat {
+    /// Compressed s1 without padding, the format of the Falcon submission
+    Compressed,
+    /// Compressed s1, zero-padded to a fixed length
+    Padded,
+    /// Coefficients of s1 at a fixed bit width, encoded in constant time
+    ConstantTime,
+    /// Padded encoding of an FN-DSA signature, under its own header so that a
+    /// legacy signature of a prefixed message is not an FN-DSA signature.
+    /// Headers are not hashed: relabelled, an FN-DSA signature still verifies
+    /// as a legacy signature of the prefixed message.
+    FnDsa,
+}
+
+impl SignatureFormat {
+    fn from_head(head: u8) -> Option<Self> {
+        match head & 0xF0 {
+            0x20 => Some(Self::Compressed),
+            0x30 => Some(Self::Padded),
+            0x40 => Some(Self::FnDsa),
+            0x50 => Some(Self::ConstantTime),
+            _ => None,
+        }
+    }
+
+    fn head(self, n: usize) -> u8 {
+        let logn = n.ilog2() as u8;
+        match self {
+            Self::Compressed => 0x20 + logn,
+            Self::Padded => 0x30 + logn,
+            Self::FnDsa => 0x40 + logn,
+            Self::ConstantTime => 0x50 + logn,
+        }
+    }
+
+    /// Length of the encoded s1, or its maximum for the compressed format
+    fn content_len(self, params: &PublicParameters) -> usize {
+        let sig_bytelen = match self {
+            Self::Compressed => params.sig_max_bytelen,
+            Self::Padded | Self::FnDsa => params.sig_bytelen,
+            Self::ConstantTime => params.sig_ct_bytelen,
+        };
+        sig_bytelen - HEAD_LEN - SALT_LEN
+    }
+
+    /// Whether an encoded s1 of `len` bytes fits the content length `slen`
+    fn accepts_len(self, len: usize, slen: usize) -> bool {
+        match self {
+            Self::Compressed => (1..=slen).contains(&len),
+            Self::Padded | Self::FnDsa | Self::ConstantTime => len == slen,
+        }
+    }
+
+    fn compress(self, v: &[i16], slen: usize) -> Option<Vec<u8>> {
+        match self {
+            Self::Compressed => {
+                // The last coefficient ends with a set bit: what follows is padding
+                let mut x = NaiveCompression::compress(v, slen)?;
+                let len = x.iter().rposition(|&b| b != 0).map_or(0, |last| last + 1);
+                x.truncate(len);
+                Some(x)
+            }
+            Self::Padded | Self::FnDsa => NaiveCompression::compress(v, slen),
+            Self::ConstantTime => ConstantTimeCompression::compress(v, slen),
+        }
+    }
+
+    fn decompress(self, x: &[u8], slen: usize, n: usize) -> Option<Vec<i16>> {
+        match self {
+            // A trailing zero byte would be padding, which this format has not
+            Self::Compressed if x.last() == Some(&0) => None,
+            Self::Compressed | Self::Padded | Self::FnDsa => {
+                NaiveDecompression::decompress(x, slen, n)
+            }
+            Self::ConstantTime => ConstantTimeCompression::decompress(x, slen, n),
+        }
     }
 }
 
@@ -129,6 +676,20 @@ struct Signature {
     content: Vec<u8>,
 }
 
+impl Signature {
+    fn format(&self) -> SignatureFormat {
+        SignatureFormat::from_head(self.head).expect("header is validated on construction")
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEAD_LEN + SALT_LEN + self.content.len());
+        bytes.push(self.head);
+        bytes.extend(self.salt);
+        bytes.extend(&self.content);
+        bytes
+    }
+}
+
 impl TryFrom<&[u8]> for Signature {
     type Error = String;
 
@@ -136,10 +697,19 @@ impl TryFrom<&[u8]> for Signature {
         let [head, salt_and_content @ ..] = value else {
             return Err("Could not parse head".into());
         };
+        let Some(format) = SignatureFormat::from_head(*head) else {
+            return Err("Unknown signature format".into());
+        };
+        let Some(params) = parameters(head & 0x0F) else {
+            return Err("Unknown parameter set".into());
+        };
         if salt_and_content.len() < SALT_LEN {
             return Err("Could not parse salt".into());
         }
         let (salt, content) = salt_and_content.split_at(SALT_LEN);
+        if !format.accepts_len(content.len(), format.content_len(&params)) {
+            return Err("Invalid signature length".into());
+        }
 
         Ok(Signature {
             head: *head,
@@ -149,8 +719,565 @@ impl TryFrom<&[u8]> for Signature {
     }
 }
 
+/// Hash to a point of a message fed in chunks, after the FN-DSA prefix if any
+struct MessageHasher {
+    salt: [u8; SALT_LEN],
+    prefix: Vec<u8>,
+    hasher: Option<PointHasher>,
+}
+
+impl MessageHasher {
+    fn new(salt: [u8; SALT_LEN]) -> Self {
+        Self {
+            salt,
+            prefix: Vec::new(),
+            hasher: None,
+        }
+    }
+
+    /// Hashes in FN-DSA mode under the context string, before any message data
+    fn context(&mut self, context: &[u8]) -> Result<(), String> {
+        if self.hasher.is_some() {
+            return Err("Context must be set before any message data".into());
+        }
+        self.prefix = fn_dsa_prefix(FN_DSA_PURE, context)?;
+        Ok(())
+    }
+
+    fn is_fn_dsa(&self) -> bool {
+        !self.prefix.is_empty()
+    }
+
+    /// Hasher of everything fed so far, the prefix alone if no data was
+    fn into_point_hasher(mut self) -> PointHasher {
+        self.update(&[]);
+        self.hasher.unwrap()
+    }
+}
+
+impl Absorb for MessageHasher {
+    fn update(&mut self, data: &[u8]) {
+        let (salt, prefix) = (&self.salt, &self.prefix);
+        self.hasher
+            .get_or_insert_with(|| {
+                let mut hasher = PointHasher::new(salt);
+                hasher.update(prefix);
+                hasher
+            })
+            .update(data);
+    }
+}
+
+impl io::Write for MessageHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Incremental signing of a message fed in chunks
+struct Signer<'a, F: Falcon, R: Rng + CryptoRng = ThreadRng> {
+    key: &'a SecretKey<F>,
+    format: SignatureFormat,
+    message: MessageHasher,
+    constant_time_hash: bool,
+    rng: R,
+}
+
+impl<'a, F: Falcon> Signer<'a, F> {
+    fn new(key: &'a SecretKey<F>) -> Self {
+        Self::with_rng(key, rng())
+    }
+}
+
+impl<'a, F: Falcon, R: Rng + CryptoRng> Signer<'a, F, R> {
+    fn with_rng(key: &'a SecretKey<F>, mut rng: R) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        rng.fill(&mut salt);
+        Self {
+            key,
+            format: SignatureFormat::Padded,
+            message: MessageHasher::new(salt),
+            constant_time_hash: false,
+            rng,
+        }
+    }
+
+    /// Format of the signature, which FN-DSA mode ignores
+    ///
+    /// Panics on `SignatureFormat::FnDsa`, which `context` selects instead
+    fn format(mut self, format: SignatureFormat) -> Self {
+        assert_ne!(format, SignatureFormat::FnDsa, "FN-DSA needs a context");
+        self.format = format;
+        self
+    }
+
+    /// Signs in FN-DSA mode under the context string, before any message data is written
+    fn context(mut self, context: &[u8]) -> Result<Self, String> {
+        self.message.context(context)?;
+        Ok(self)
+    }
+
+    /// Hashes the message to a point in constant time, for secret messages
+    fn constant_time_hash(mut self, enabled: bool) -> Self {
+        self.constant_time_hash = enabled;
+        self
+    }
+
+    fn finalize(mut self) -> Signature {
+        let salt = self.message.salt;
+        let format = if self.message.is_fn_dsa() {
+            SignatureFormat::FnDsa
+        } else {
+            self.format
+        };
+        let hasher = self.message.into_point_hasher();
+        let hashed = if self.constant_time_hash {
+            hasher.finalize_ct(F::PARAMETERS.n)
+        } else {
+            hasher.finalize(F::PARAMETERS.n)
+        };
+        self.key.sign_hashed(salt, &hashed, format, &mut self.rng)
+    }
+}
+
+impl<F: Falcon, R: Rng + CryptoRng> Absorb for Signer<'_, F, R> {
+    fn update(&mut self, data: &[u8]) {
+        self.message.update(data);
+    }
+}
+
+impl<F: Falcon, R: Rng + CryptoRng> io::Write for Signer<'_, F, R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.message.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Incremental verification of a message fed in chunks
+struct Verifier<'a, F: Falcon> {
+    key: &'a PublicKey<F>,
+    signature: Signature,
+    message: MessageHasher,
+}
+
+impl<'a, F: Falcon> Verifier<'a, F> {
+    fn new(key: &'a PublicKey<F>, signature: Signature) -> Self {
+        Self {
+            key,
+            message: MessageHasher::new(signature.salt),
+            signature,
+        }
+    }
+
+    /// Verifies in FN-DSA mode under the context string, before any message data is written
+    fn context(mut self, context: &[u8]) -> Result<Self, String> {
+        self.message.context(context)?;
+        Ok(self)
+    }
+
+    fn finalize(self) -> bool {
+        let fn_dsa = self.signature.format() == SignatureFormat::FnDsa;
+        if self.message.is_fn_dsa() != fn_dsa {
+            return false;
+        }
+        let hashed = self.message.into_point_hasher().finalize(F::PARAMETERS.n);
+        self.key.verify_hashed(hashed, &self.signature)
+    }
+}
+
+impl<F: Falcon> Absorb for Verifier<'_, F> {
+    fn update(&mut self, data: &[u8]) {
+        self.message.update(data);
+    }
+}
+
+impl<F: Falcon> io::Write for Verifier<'_, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.message.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 fn keygen<F: Falcon>(seed: Seed) -> (SecretKey<F>, PublicKey<F>) {
     let sk = SecretKey::from(seed);
-    let pk = PublicKey::from(&sk);
+    let pk = PublicKey::try_from(&sk).expect("ntru_gen only yields invertible f");
     (sk, pk)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use super::*;
+
+    fn falcon512() -> &'static (SecretKey<Falcon512>, PublicKey<Falcon512>) {
+        static KEYS: OnceLock<(SecretKey<Falcon512>, PublicKey<Falcon512>)> = OnceLock::new();
+        KEYS.get_or_init(|| keygen([1u8; SEED_LEN]))
+    }
+
+    fn falcon1024() -> &'static (SecretKey<Falcon1024>, PublicKey<Falcon1024>) {
+        static KEYS: OnceLock<(SecretKey<Falcon1024>, PublicKey<Falcon1024>)> = OnceLock::new();
+        KEYS.get_or_init(|| keygen([2u8; SEED_LEN]))
+    }
+
+    fn encode_public_key(logn: u8, h: &[i16]) -> Vec<u8> {
+        let mut bytes = vec![logn];
+        bytes.extend(pack_bits(h, PUBLIC_KEY_BITS));
+        bytes
+    }
+
+    #[test]
+    fn public_key_round_trip() {
+        let (_, pk) = falcon512();
+        let bytes = pk.to_bytes();
+        assert_eq!(bytes.len(), 897);
+        assert_eq!(bytes[0], 0x09);
+        let decoded = PublicKey::<Falcon512>::try_from(&bytes[..]).unwrap();
+        assert_eq!(decoded.h.coefficients, pk.h.coefficients);
+
+        let (_, pk) = falcon1024();
+        let bytes = pk.to_bytes();
+        assert_eq!(bytes.len(), 1793);
+        assert_eq!(bytes[0], 0x0A);
+        let decoded = PublicKey::<Falcon1024>::try_from(&bytes[..]).unwrap();
+        assert_eq!(decoded.h.coefficients, pk.h.coefficients);
+    }
+
+    #[test]
+    fn public_key_rejects_invalid_encodings() {
+        let (_, pk) = falcon512();
+        let bytes = pk.to_bytes();
+        let decode = |bytes: &[u8]| PublicKey::<Falcon512>::try_from(bytes).is_ok();
+
+        for head in [0x0A, 0x19, 0x59] {
+            let mut wrong_head = bytes.clone();
+            wrong_head[0] = head;
+            assert!(!decode(&wrong_head), "head = {head:#x}");
+        }
+        assert!(PublicKey::<Falcon1024>::try_from(&bytes[..]).is_err());
+        assert!(!decode(&bytes[..bytes.len() - 1]));
+        assert!(!decode(&[&bytes[..], &[0]].concat()));
+        assert!(!decode(&[]));
+
+        let mut h = pk.h.coefficients.clone();
+        h[5] = Q as i16 - 1;
+        assert!(decode(&encode_public_key(9, &h)));
+        for c in [Q as i16, (1 << PUBLIC_KEY_BITS) - 1] {
+            h[5] = c;
+            assert!(!decode(&encode_public_key(9, &h)), "coefficient {c}");
+        }
+    }
+
+    fn encode_secret_key<F: Falcon>(polys: &[&Polynomial<i16>; 3]) -> Vec<u8> {
+        let params = F::PARAMETERS;
+        let [f, g, big_f] = polys;
+        let mut bytes = vec![0x50 + params.n.ilog2() as u8];
+        bytes.extend(pack_bits(&f.coefficients, params.fg_bits));
+        bytes.extend(pack_bits(&g.coefficients, params.fg_bits));
+        bytes.extend(pack_bits(&big_f.coefficients, BIG_FG_BITS));
+        bytes
+    }
+
+    fn assert_secret_key_round_trip<F: Falcon>(sk: &SecretKey<F>, len: usize) {
+        let bytes = sk.to_bytes();
+        assert_eq!(bytes.len(), len);
+        assert_eq!(bytes[0], 0x50 + F::PARAMETERS.n.ilog2() as u8);
+        let decoded = SecretKey::<F>::try_from(&bytes[..]).unwrap();
+        // G is not encoded: it is recomputed from fG - gF = q
+        for (decoded, original) in decoded.polys.iter().zip(&sk.polys) {
+            assert_eq!(decoded.coefficients, original.coefficients);
+        }
+        assert_eq!(decoded.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn secret_key_round_trip() {
+        assert_secret_key_round_trip(&falcon512().0, 1281);
+        assert_secret_key_round_trip(&falcon1024().0, 2305);
+    }
+
+    #[test]
+    fn secret_key_rejects_invalid_encodings() {
+        let (sk, _) = falcon512();
+        let bytes = sk.to_bytes();
+        let decode = |bytes: &[u8]| SecretKey::<Falcon512>::try_from(bytes).is_ok();
+
+        for head in [0x5A, 0xD9, 0x09] {
+            let mut wrong_head = bytes.clone();
+            wrong_head[0] = head;
+            assert!(!decode(&wrong_head), "head = {head:#x}");
+        }
+        assert!(SecretKey::<Falcon1024>::try_from(&bytes[..]).is_err());
+        assert!(!decode(&bytes[..bytes.len() - 1]));
+        assert!(!decode(&[&bytes[..], &[0]].concat()));
+
+        // -2^(bits - 1) is not a valid coefficient of f, g or F
+        let [f, g, big_f, _] = &sk.polys;
+        assert!(decode(&encode_secret_key::<Falcon512>(&[f, g, big_f])));
+        let mut f_min = f.clone();
+        f_min.coefficients[3] = -(1 << (Falcon512::PARAMETERS.fg_bits - 1));
+        let encoded = encode_secret_key::<Falcon512>(&[&f_min, g, big_f]);
+        assert!(!decode(&encoded));
+        let mut big_f_min = big_f.clone();
+        big_f_min.coefficients[3] = -(1 << (BIG_FG_BITS - 1));
+        let encoded = encode_secret_key::<Falcon512>(&[f, g, &big_f_min]);
+        assert!(!decode(&encoded));
+
+        // f must be invertible mod q for G to be recomputed
+        let zero: Polynomial<i16> = std::iter::repeat_n(0, 512).collect();
+        assert!(!decode(&encode_secret_key::<Falcon512>(&[&zero, g, big_f])));
+    }
+
+    #[test]
+    fn compressed_signatures() {
+        let (sk, pk) = falcon512();
+        let message = b"compressed";
+        for format in [
+            SignatureFormat::Compressed,
+            SignatureFormat::Padded,
+            SignatureFormat::ConstantTime,
+        ] {
+            let bytes = sk.sign(message, format).to_bytes();
+            let signature = Signature::try_from(&bytes[..]).unwrap();
+            assert_eq!(signature.format(), format);
+            assert_eq!(signature.to_bytes(), bytes);
+            assert!(pk.verify(message, signature), "{format:?}");
+        }
+
+        let signature = sk.sign(message, SignatureFormat::Compressed);
+        assert_eq!(signature.head, 0x29);
+        assert_ne!(signature.content.last(), Some(&0));
+        let bytes = signature.to_bytes();
+        assert!(bytes.len() < Falcon512::PARAMETERS.sig_bytelen);
+
+        // The padded format holds the same encoding, followed by zeros
+        let mut padded = bytes.clone();
+        padded[0] = 0x39;
+        padded.resize(Falcon512::PARAMETERS.sig_bytelen, 0);
+        assert!(pk.verify(message, Signature::try_from(&padded[..]).unwrap()));
+
+        // Padding a compressed signature makes it non-canonical
+        let padded = [&bytes[..], &[0]].concat();
+        let signature = Signature::try_from(&padded[..]).unwrap();
+        assert!(!pk.verify(message, signature));
+
+        let too_long = vec![0x29; HEAD_LEN + SALT_LEN + 753];
+        assert!(Signature::try_from(&too_long[..]).is_err());
+        assert!(Signature::try_from(&bytes[..HEAD_LEN + SALT_LEN]).is_err());
+        assert!(Signature::try_from(&bytes[..HEAD_LEN + SALT_LEN - 1]).is_err());
+
+        let (sk, pk) = falcon1024();
+        let signature = sk.sign(message, SignatureFormat::Compressed);
+        assert_eq!(signature.head, 0x2A);
+        assert!(pk.verify(message, signature));
+    }
+
+    #[test]
+    fn fn_dsa_signatures() {
+        let (sk, pk) = falcon512();
+        let message = b"fn-dsa";
+        let signature = sk.sign_fn_dsa(message, b"context").unwrap();
+        let bytes = signature.to_bytes();
+        assert_eq!(bytes.len(), Falcon512::PARAMETERS.sig_bytelen);
+        assert_eq!(bytes[0], 0x49);
+        assert!(pk.verify_fn_dsa(message, b"context", signature));
+
+        let verify = |context: &[u8]| {
+            let signature = Signature::try_from(&bytes[..]).unwrap();
+            pk.verify_fn_dsa(message, context, signature)
+        };
+        assert!(!verify(b"contexts"));
+        assert!(!verify(b"Context"));
+        assert!(!verify(b""));
+
+        // Pure Falcon and FN-DSA sign different messages
+        assert!(!pk.verify(message, Signature::try_from(&bytes[..]).unwrap()));
+        let signature = sk.sign(message, SignatureFormat::Padded);
+        assert!(!pk.verify_fn_dsa(message, b"", signature));
+        let signature = sk.sign_fn_dsa(message, b"").unwrap();
+        assert!(!pk.verify(message, signature));
+
+        // A legacy signature of the prefixed message is not an FN-DSA signature
+        let prefix = fn_dsa_prefix(FN_DSA_PURE, b"context").unwrap();
+        let prefixed = [&prefix[..], message].concat();
+        let signature = sk.sign(&prefixed, SignatureFormat::Padded);
+        assert!(!pk.verify_fn_dsa(message, b"context", signature));
+        let signature = sk.sign_fn_dsa(message, b"context").unwrap();
+        assert!(!pk.verify(&prefixed, signature));
+
+        // The same s1 without padding is a valid encoding, but not an FN-DSA one
+        let signature = Signature::try_from(&bytes[..]).unwrap();
+        let content_len = signature.content.iter().rposition(|&b| b != 0).unwrap() + 1;
+        let compressed = Signature {
+            head: SignatureFormat::Compressed.head(512),
+            salt: signature.salt,
+            content: signature.content[..content_len].to_vec(),
+        };
+        let prefix = fn_dsa_prefix(FN_DSA_PURE, b"context").unwrap();
+        let hashed =
+            Polynomial::<Zq>::hash_to_point_parts(&[&prefix, message], &compressed.salt, 512);
+        assert!(pk.verify_hashed(hashed, &compressed));
+        assert!(!pk.verify_fn_dsa(message, b"context", compressed));
+
+        let long_context = [0u8; CONTEXT_MAX_LEN + 1];
+        let signature = sk.sign_fn_dsa(message, &long_context[1..]).unwrap();
+        assert!(pk.verify_fn_dsa(message, &long_context[1..], signature));
+        assert!(sk.sign_fn_dsa(message, &long_context).is_err());
+        let signature = Signature::try_from(&bytes[..]).unwrap();
+        assert!(!pk.verify_fn_dsa(message, &long_context, signature));
+
+        // The streaming API signs FN-DSA messages in the same format
+        let mut signer = Signer::new(sk)
+            .format(SignatureFormat::ConstantTime)
+            .context(b"context")
+            .unwrap();
+        signer.update(message);
+        let signature = signer.finalize();
+        assert_eq!(signature.format(), SignatureFormat::FnDsa);
+        assert!(pk.verify_fn_dsa(message, b"context", signature));
+        let signature = sk.sign(message, SignatureFormat::ConstantTime);
+        let mut verifier = Verifier::new(pk, signature).context(b"").unwrap();
+        verifier.update(message);
+        assert!(!verifier.finalize());
+
+        let (sk, pk) = falcon1024();
+        let signature = sk.sign_fn_dsa(message, b"context").unwrap();
+        assert_eq!(signature.head, 0x4A);
+        assert_eq!(signature.content.len(), 1280 - HEAD_LEN - SALT_LEN);
+        assert!(pk.verify_fn_dsa(message, b"context", signature));
+    }
+
+    #[test]
+    fn prehashed_signatures_bind_the_hash_function() {
+        let (sk, pk) = falcon512();
+        let message = b"firmware image";
+        let mut hasher = PreHash::Sha256.hasher();
+        for chunk in message.chunks(3) {
+            hasher.update(chunk);
+        }
+        let digest = hasher.finalize();
+        let sign =
+            |digest: &[u8], hash: PreHash, context: &[u8]| sk.sign_prehashed(digest, hash, context);
+        let signature = sign(&digest, PreHash::Sha256, b"context").unwrap();
+        let bytes = signature.to_bytes();
+        assert_eq!(signature.format(), SignatureFormat::FnDsa);
+        let verify = |digest: &[u8], hash: PreHash, context: &[u8]| {
+            let signature = Signature::try_from(&bytes[..]).unwrap();
+            pk.verify_prehashed(digest, hash, context, signature)
+        };
+        assert!(verify(&digest, PreHash::Sha256, b"context"));
+
+        // SHA3-256 has the same digest length, but another OID
+        assert!(!verify(&digest, PreHash::Sha3_256, b"context"));
+        assert!(!verify(&digest, PreHash::Shake128, b"context"));
+        assert!(!verify(&digest, PreHash::Sha256, b""));
+        let other_digest = PreHash::Sha256.digest(b"firmware imag");
+        assert!(!verify(&other_digest, PreHash::Sha256, b"context"));
+        assert!(!verify(&digest[..31], PreHash::Sha256, b"context"));
+        let signature = Signature::try_from(&bytes[..]).unwrap();
+        assert!(!pk.verify_fn_dsa(&digest, b"context", signature));
+
+        // Nor is a legacy signature of the pre-hash message representative
+        let prefix = prehash_prefix(PreHash::Sha256, &digest, b"context").unwrap();
+        let representative = [&prefix[..], &digest].concat();
+        let signature = sk.sign(&representative, SignatureFormat::Padded);
+        assert!(!pk.verify_prehashed(&digest, PreHash::Sha256, b"context", signature));
+
+        assert!(sign(&digest, PreHash::Sha512, b"").is_err());
+        assert!(sign(&digest[..31], PreHash::Sha256, b"").is_err());
+        let long_context = [0u8; CONTEXT_MAX_LEN + 1];
+        assert!(sign(&digest, PreHash::Sha256, &long_context).is_err());
+
+        for hash in [PreHash::Sha512, PreHash::Sha3_256, PreHash::Shake256] {
+            let digest = hash.digest(message);
+            let signature = sign(&digest, hash, b"").unwrap();
+            assert!(pk.verify_prehashed(&digest, hash, b"", signature));
+        }
+    }
+
+    #[test]
+    fn streamed_signatures_match_one_shot_signatures() {
+        use std::io::Write;
+
+        let (sk, pk) = falcon512();
+        let message: Vec<u8> = (0..5000u32).map(|i| (i * 31 + 7) as u8).collect();
+        let rng = || Shake256Rng::from_seed(b"streaming");
+        let format = SignatureFormat::Padded;
+        let expected = sk.sign_rng(&message, format, rng()).to_bytes();
+
+        let mut chunked = Signer::with_rng(sk, rng());
+        for chunk in message.chunks(333) {
+            chunked.update(chunk);
+        }
+        let mut written = Signer::with_rng(sk, rng()).constant_time_hash(true);
+        for chunk in message.chunks(1000) {
+            written.write_all(chunk).unwrap();
+        }
+        let mut read = Signer::with_rng(sk, rng());
+        assert_eq!(read.update_reader(&message[..]).unwrap(), 5000);
+        for signer in [chunked, written, read] {
+            let signature = signer.finalize();
+            assert_eq!(signature.to_bytes(), expected);
+            assert!(pk.verify(&message, signature));
+        }
+
+        // No data at all signs the empty message
+        let signature = Signer::with_rng(sk, rng()).finalize();
+        let empty = sk.sign_rng(b"", format, rng());
+        assert_eq!(signature.to_bytes(), empty.to_bytes());
+
+        let signature = || Signature::try_from(&expected[..]).unwrap();
+        let mut verifier = Verifier::new(pk, signature());
+        for chunk in message.chunks(333) {
+            verifier.update(chunk);
+        }
+        assert!(verifier.finalize());
+        let mut verifier = Verifier::new(pk, signature());
+        verifier.write_all(&message).unwrap();
+        assert!(verifier.finalize());
+        let mut verifier = Verifier::new(pk, signature());
+        verifier.update_reader(&message[..4999]).unwrap();
+        assert!(!verifier.finalize());
+    }
+
+    #[test]
+    fn streamed_context_precedes_data() {
+        let (sk, pk) = falcon512();
+        let rng = || Shake256Rng::from_seed(b"context");
+        let expected = sk.sign_fn_dsa_rng(b"message", b"ctx", rng()).unwrap();
+
+        let mut signer = Signer::with_rng(sk, rng()).context(b"ctx").unwrap();
+        signer.update(b"mess");
+        signer.update(b"age");
+        assert_eq!(signer.finalize().to_bytes(), expected.to_bytes());
+
+        let mut signer = Signer::new(sk);
+        signer.update(b"mess");
+        assert!(signer.context(b"ctx").is_err());
+        // Empty data counts as data too
+        let mut signer = Signer::new(sk);
+        signer.update(b"");
+        assert!(signer.context(b"ctx").is_err());
+        let long_context = [0u8; CONTEXT_MAX_LEN + 1];
+        assert!(Signer::new(sk).context(&long_context).is_err());
+
+        let mut verifier = Verifier::new(pk, expected).context(b"ctx").unwrap();
+        verifier.update(b"message");
+        assert!(verifier.finalize());
+        let signature = sk.sign_fn_dsa(b"message", b"ctx").unwrap();
+        let mut verifier = Verifier::new(pk, signature);
+        verifier.update(b"mess");
+        assert!(verifier.context(b"ctx").is_err());
+    }
+}
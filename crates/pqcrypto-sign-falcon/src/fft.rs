@@ -1,7 +1,21 @@
+#![allow(dead_code)]
+
 use crate::common::Polynomial;
+use crate::simd;
 
 include!(concat!(env!("OUT_DIR"), "/fft_constants.rs"));
 
+impl<T: Copy + Into<f64>> From<&Polynomial<T>> for Polynomial<Complex64> {
+    fn from(value: &Polynomial<T>) -> Self {
+        value
+            .coefficients
+            .iter()
+            .map(|&c| Complex64::new(c.into(), 0.))
+            .collect()
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
 pub trait FFT: Sized {
     fn split_fft(self) -> (Self, Self);
     fn merge_fft(self, other: Self) -> Self;
@@ -20,67 +34,74 @@ pub trait FFT: Sized {
     fn adj_fft(self) -> Self;
 }
 
+/// Iterative in-place FFT, evaluating a at roots(n) in the same order as the recursive FFT
+pub fn fft_in_place(a: &mut [Complex64]) {
+    simd::fft(a, &FFT_TWIDDLES);
+}
+
+/// Iterative in-place inverse of `fft_in_place`
+pub fn ifft_in_place(a: &mut [Complex64]) {
+    let n = a.len();
+    simd::ifft(a, &FFT_TWIDDLES);
+
+    let inv_n = 1. / n as f64;
+    for x in a {
+        *x *= inv_n;
+    }
+}
+
 impl FFT for Polynomial<Complex64> {
     fn split_fft(self) -> (Self, Self) {
         let n = self.len();
-        let w = roots(n as u16);
-        self.coefficients
-            .chunks_exact(2)
-            .enumerate()
-            .map(|(i, chunks)| {
-                let [even, odd] = chunks else { unreachable!() };
-                let f0_fft = (even + odd) * 0.5;
-                let f1_fft = (even - odd) * 0.5 * w[2 * i].conj();
-                (f0_fft, f1_fft)
-            })
-            .unzip()
+        let mut f0_fft = vec![Complex64::default(); n / 2];
+        let mut f1_fft = vec![Complex64::default(); n / 2];
+        simd::split_fft(
+            &self.coefficients,
+            roots(n as u16),
+            &mut f0_fft,
+            &mut f1_fft,
+        );
+        (
+            Polynomial {
+                coefficients: f0_fft,
+            },
+            Polynomial {
+                coefficients: f1_fft,
+            },
+        )
     }
 
     fn merge_fft(self, other: Self) -> Self {
         let n = 2 * self.len();
-        let w = roots(n as u16);
-        self.coefficients
-            .iter()
-            .enumerate()
-            .zip(other.coefficients)
-            .flat_map(|((i, a), b)| [a + w[2 * i] * b, a - w[2 * i] * b])
-            .collect()
+        let mut f_fft = vec![Complex64::default(); n];
+        simd::merge_fft(
+            &self.coefficients,
+            &other.coefficients,
+            roots(n as u16),
+            &mut f_fft,
+        );
+        Polynomial {
+            coefficients: f_fft,
+        }
     }
 
-    fn fft(self) -> Self {
-        match self.coefficients.as_slice() {
-            [f0, f1] => Polynomial {
-                coefficients: vec![
-                    f0 + Complex64::new(0., 1.) * f1,
-                    f0 - Complex64::new(0., 1.) * f1,
-                ]
-            },
-            _ => {
-                let (f0, f1) = self.split();
-                let f0_fft = f0.fft();
-                let f1_fft = f1.fft();
-                f0_fft.merge_fft(f1_fft)
-            }
-        }
+    fn fft(mut self) -> Self {
+        fft_in_place(&mut self.coefficients);
+        self
     }
 
-    fn ifft(self) -> Self {
-        match self.coefficients.as_slice() {
-            [f0_fft, _] => Polynomial {
-                coefficients: vec![Complex64::new(f0_fft.re, 0.), Complex64::new(f0_fft.im, 0.)]
-            },
-            _ => {
-                let (f0_fft, f1_fft) = self.split_fft();
-                let f0 = f0_fft.ifft();
-                let f1 = f1_fft.ifft();
-                f0.merge(f1)
-            }
+    /// The polynomial is real, so the imaginary parts are dropped
+    fn ifft(mut self) -> Self {
+        ifft_in_place(&mut self.coefficients);
+        for c in &mut self.coefficients {
+            c.im = 0.;
         }
+        self
     }
 
     fn add(self, other: Self) -> Self {
         self.coefficients.into_iter()
-            .zip(other.coefficients.into_iter())
+            .zip(other.coefficients)
             .map(|(a, b)| a + b)
             .collect()
     }
@@ -91,7 +112,7 @@ impl FFT for Polynomial<Complex64> {
 
     fn sub(self, other: Self) -> Self {
         self.coefficients.into_iter()
-            .zip(other.coefficients.into_iter())
+            .zip(other.coefficients)
             .map(|(a, b)| a - b)
             .collect()
     }
@@ -120,16 +141,14 @@ impl FFT for Polynomial<Complex64> {
         self.sub(other)
     }
 
-    fn mul_fft(self, other: Self) -> Self {
-        self.coefficients.into_iter()
-            .zip(other.coefficients.into_iter())
-            .map(|(a, b)| a * b)
-            .collect()
+    fn mul_fft(mut self, other: Self) -> Self {
+        simd::mul_fft(&mut self.coefficients, &other.coefficients);
+        self
     }
 
     fn div_fft(self, other: Self) -> Self {
         self.coefficients.into_iter()
-            .zip(other.coefficients.into_iter())
+            .zip(other.coefficients)
             .map(|(a, b)| a / b)
             .collect()
     }
@@ -140,3 +159,72 @@ impl FFT for Polynomial<Complex64> {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    /// The original recursive FFT, kept as an oracle
+    fn fft_recursive(f: Polynomial<Complex64>) -> Polynomial<Complex64> {
+        match f.coefficients.as_slice() {
+            [f0, f1] => Polynomial {
+                coefficients: vec![
+                    f0 + Complex64::new(0., 1.) * f1,
+                    f0 - Complex64::new(0., 1.) * f1,
+                ],
+            },
+            _ => {
+                let (f0, f1) = f.split();
+                fft_recursive(f0).merge_fft(fft_recursive(f1))
+            }
+        }
+    }
+
+    /// The original recursive inverse FFT, kept as an oracle
+    fn ifft_recursive(f_fft: Polynomial<Complex64>) -> Polynomial<Complex64> {
+        match f_fft.coefficients.as_slice() {
+            [f0_fft, _] => Polynomial {
+                coefficients: vec![Complex64::new(f0_fft.re, 0.), Complex64::new(f0_fft.im, 0.)],
+            },
+            _ => {
+                let (f0_fft, f1_fft) = f_fft.split_fft();
+                ifft_recursive(f0_fft).merge(ifft_recursive(f1_fft))
+            }
+        }
+    }
+
+    fn random_real_poly(n: usize, rng: &mut StdRng) -> Polynomial<Complex64> {
+        (0..n)
+            .map(|_| Complex64::new(rng.random_range(-4096.0..4096.0), 0.))
+            .collect()
+    }
+
+    fn assert_close(a: &Polynomial<Complex64>, b: &Polynomial<Complex64>) {
+        for (x, y) in a.coefficients.iter().zip(&b.coefficients) {
+            assert!((x - y).norm() < 1e-6, "{x} != {y}");
+        }
+    }
+
+    #[test]
+    fn in_place_fft_matches_recursive() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for logn in 1..=10 {
+            let f = random_real_poly(1 << logn, &mut rng);
+            let f_fft = fft_recursive(f.clone());
+            assert_close(&f.fft(), &f_fft);
+            assert_close(&f_fft.clone().ifft(), &ifft_recursive(f_fft));
+        }
+    }
+
+    #[test]
+    fn in_place_fft_roundtrip() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for logn in 1..=10 {
+            let f = random_real_poly(1 << logn, &mut rng);
+            assert_close(&f.clone().fft().ifft(), &f);
+        }
+    }
+}
@@ -4,6 +4,12 @@ mod fft;
 mod encoding;
 mod samplerz;
 mod ntt;
+mod ntrugen;
+mod ntrusolve;
+mod prehash;
+mod prng;
+mod simd;
+mod zq;
 
 fn main() {
     println!("Hello world!");
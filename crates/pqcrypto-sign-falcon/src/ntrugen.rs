@@ -0,0 +1,128 @@
+#![allow(dead_code)]
+
+use num::bigint::BigInt;
+use num::ToPrimitive;
+use num_complex::Complex64;
+use rand::{CryptoRng, Rng};
+
+use crate::common::{Polynomial, Q};
+use crate::falcon::Polynomials;
+use crate::fft::FFT;
+use crate::ntrusolve::ntru_solve;
+use crate::ntt::NTT;
+use crate::samplerz::sampler_z_ct;
+use crate::zq::Zq;
+
+/// Standard deviation of the samples, 1.17 * sqrt(q / 8192)
+const SIGMA_FG: f64 = 1.43300980528773;
+/// Lower bound on the sampler's sigma, SIGMA_FG - 0.001 as in the reference gen_poly
+const SIGMIN_FG: f64 = SIGMA_FG - 0.001;
+const SAMPLES: usize = 4096;
+
+fn gen_poly<R>(n: usize, rng: &mut R) -> Polynomial<i16>
+where
+    R: Rng + CryptoRng,
+{
+    debug_assert!(n < SAMPLES);
+    let k = SAMPLES / n;
+    (0..n)
+        .map(|_| {
+            (0..k)
+                .map(|_| sampler_z_ct(0f64, SIGMA_FG, SIGMIN_FG, rng))
+                .sum::<i32>() as i16
+        })
+        .collect()
+}
+
+fn to_fft(p: &Polynomial<i16>) -> Polynomial<Complex64> {
+    Polynomial::<Complex64>::from(p).fft()
+}
+
+/// Gram-Schmidt norm of the NTRU basis generated by f and g
+fn gs_norm(f: &Polynomial<i16>, g: &Polynomial<i16>) -> f64 {
+    let sqnorm_fg: f64 = f
+        .coefficients
+        .iter()
+        .chain(&g.coefficients)
+        .map(|&c| (c as f64).powi(2))
+        .sum();
+
+    let f_fft = to_fft(f);
+    let g_fft = to_fft(g);
+    let ffgg_fft = f_fft
+        .clone()
+        .mul_fft(f_fft.clone().adj_fft())
+        .add_fft(g_fft.clone().mul_fft(g_fft.clone().adj_fft()));
+    let ft = g_fft.adj_fft().div_fft(ffgg_fft.clone()).ifft();
+    let gt = f_fft.adj_fft().div_fft(ffgg_fft).ifft();
+    let sqnorm_fg_tilde: f64 = ft
+        .coefficients
+        .iter()
+        .chain(&gt.coefficients)
+        .map(|c| c.norm_sqr())
+        .sum();
+
+    sqnorm_fg.max((Q * Q) as f64 * sqnorm_fg_tilde)
+}
+
+fn is_invertible(f: &Polynomial<i16>) -> bool {
+    let one: Polynomial<Zq> = std::iter::once(Zq::ONE)
+        .chain(std::iter::repeat_n(Zq::ZERO, f.len() - 1))
+        .collect();
+    one.div(Polynomial::from(f)).is_some()
+}
+
+fn to_big(p: &Polynomial<i16>) -> Polynomial<BigInt> {
+    p.coefficients.iter().map(|&c| BigInt::from(c)).collect()
+}
+
+fn from_big(p: &Polynomial<BigInt>) -> Option<Polynomial<i16>> {
+    p.coefficients.iter().map(ToPrimitive::to_i16).collect()
+}
+
+/// Generates the secret polynomials [f, g, F, G] satisfying fG - gF = q
+pub(crate) fn ntru_gen<R>(n: usize, rng: &mut R) -> Polynomials
+where
+    R: Rng + CryptoRng,
+{
+    loop {
+        let f = gen_poly(n, rng);
+        let g = gen_poly(n, rng);
+        if gs_norm(&f, &g) > 1.17 * 1.17 * Q as f64 {
+            continue;
+        }
+        if !is_invertible(&f) {
+            continue;
+        }
+        let Some((big_f, big_g)) = ntru_solve(to_big(&f), to_big(&g)) else {
+            continue;
+        };
+        let (Some(big_f), Some(big_g)) = (from_big(&big_f), from_big(&big_g)) else {
+            continue;
+        };
+        return [f, g, big_f, big_g];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::ntrusolve::BigArithmetic;
+
+    #[test]
+    fn ntru_gen_solves_ntru_equation() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for n in [512, 1024] {
+            let [f, g, big_f, big_g] = ntru_gen(n, &mut rng);
+            let lhs = to_big(&f)
+                .mul(to_big(&big_g))
+                .sub(to_big(&g).mul(to_big(&big_f)));
+            let mut q = vec![BigInt::from(0); n];
+            q[0] = BigInt::from(Q);
+            assert_eq!(lhs.coefficients, q, "n = {n}");
+        }
+    }
+}
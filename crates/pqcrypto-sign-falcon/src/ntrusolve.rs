@@ -0,0 +1,301 @@
+#![allow(dead_code)]
+
+use num::bigint::BigInt;
+use num::{Integer, One, ToPrimitive, Zero};
+use num_complex::Complex64;
+
+use crate::common::{Polynomial, Q};
+use crate::fft::FFT;
+
+/// Below this length Karatsuba falls back to schoolbook multiplication
+const KARATSUBA_THRESHOLD: usize = 16;
+
+/// Number of significant bits kept when approximating big coefficients with floats
+const FLOAT_PRECISION: u64 = 53;
+
+/// Arithmetic in Z[x]/(x^n + 1) over big-integer coefficients
+pub trait BigArithmetic: Sized {
+    fn add(self, other: Self) -> Self;
+    fn sub(self, other: Self) -> Self;
+    fn mul(self, other: Self) -> Self;
+    fn galois_conjugate(self) -> Self;
+    fn field_norm(self) -> Self;
+    fn lift(self) -> Self;
+    fn bitsize(&self) -> u64;
+}
+
+/// Full product of a and b of equal power-of-two length n, of length 2n
+fn karatsuba(a: &[BigInt], b: &[BigInt]) -> Vec<BigInt> {
+    let n = a.len();
+    let mut ab = vec![BigInt::zero(); 2 * n];
+
+    if n <= KARATSUBA_THRESHOLD {
+        for (i, ai) in a.iter().enumerate() {
+            for (abij, bj) in ab[i..].iter_mut().zip(b) {
+                *abij += ai * bj;
+            }
+        }
+        return ab;
+    }
+
+    let n2 = n / 2;
+    let (a0, a1) = a.split_at(n2);
+    let (b0, b1) = b.split_at(n2);
+    let ax: Vec<_> = a0.iter().zip(a1).map(|(x, y)| x + y).collect();
+    let bx: Vec<_> = b0.iter().zip(b1).map(|(x, y)| x + y).collect();
+
+    let a0b0 = karatsuba(a0, b0);
+    let a1b1 = karatsuba(a1, b1);
+    let mut axbx = karatsuba(&ax, &bx);
+    for ((c, lo), hi) in axbx.iter_mut().zip(&a0b0).zip(&a1b1) {
+        *c -= lo + hi;
+    }
+
+    for (c, lo) in ab.iter_mut().zip(a0b0) {
+        *c += lo;
+    }
+    for (c, hi) in ab[n..].iter_mut().zip(a1b1) {
+        *c += hi;
+    }
+    for (c, mid) in ab[n2..].iter_mut().zip(axbx) {
+        *c += mid;
+    }
+    ab
+}
+
+impl BigArithmetic for Polynomial<BigInt> {
+    fn add(self, other: Self) -> Self {
+        self.coefficients
+            .into_iter()
+            .zip(other.coefficients)
+            .map(|(a, b)| a + b)
+            .collect()
+    }
+
+    fn sub(self, other: Self) -> Self {
+        self.coefficients
+            .into_iter()
+            .zip(other.coefficients)
+            .map(|(a, b)| a - b)
+            .collect()
+    }
+
+    fn mul(self, other: Self) -> Self {
+        let n = self.len();
+        let mut ab = karatsuba(&self.coefficients, &other.coefficients);
+        let high = ab.split_off(n);
+        ab.into_iter().zip(high).map(|(lo, hi)| lo - hi).collect()
+    }
+
+    /// a(x) -> a(-x)
+    fn galois_conjugate(self) -> Self {
+        self.coefficients
+            .into_iter()
+            .enumerate()
+            .map(|(i, c)| if i % 2 == 0 { c } else { -c })
+            .collect()
+    }
+
+    /// N(a)(x^2) = a(x) * a(-x), computed as a_even^2 - x * a_odd^2
+    fn field_norm(self) -> Self {
+        let (a_even, a_odd) = self.split();
+        let a_even_squared = a_even.clone().mul(a_even);
+        let a_odd_squared = a_odd.clone().mul(a_odd);
+        let mut x_a_odd_squared = a_odd_squared.coefficients;
+        x_a_odd_squared.rotate_right(1);
+        x_a_odd_squared[0] = -std::mem::take(&mut x_a_odd_squared[0]);
+        a_even_squared.sub(x_a_odd_squared.into_iter().collect())
+    }
+
+    /// a(x) -> a(x^2)
+    fn lift(self) -> Self {
+        self.coefficients
+            .into_iter()
+            .flat_map(|c| [c, BigInt::zero()])
+            .collect()
+    }
+
+    /// Size of the largest coefficient, rounded up to a multiple of 8
+    fn bitsize(&self) -> u64 {
+        self.coefficients
+            .iter()
+            .map(|c| c.bits().div_ceil(8) * 8)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// The most significant bits of the coefficients of a, in FFT representation
+fn adjusted_fft(a: &Polynomial<BigInt>, size: u64) -> Polynomial<Complex64> {
+    a.coefficients
+        .iter()
+        .map(|c| {
+            let adjusted = c >> (size - FLOAT_PRECISION);
+            Complex64::new(adjusted.to_f64().unwrap(), 0f64)
+        })
+        .collect::<Polynomial<_>>()
+        .fft()
+}
+
+/// Babai reduction of (F, G) with respect to (f, g)
+///
+/// F and G are shrunk by exact multiples of f and g, so fG - gF is preserved.
+fn reduce(
+    f: &Polynomial<BigInt>,
+    g: &Polynomial<BigInt>,
+    mut big_f: Polynomial<BigInt>,
+    mut big_g: Polynomial<BigInt>,
+) -> (Polynomial<BigInt>, Polynomial<BigInt>) {
+    let size = f.bitsize().max(g.bitsize()).max(FLOAT_PRECISION);
+    let fa_fft = adjusted_fft(f, size);
+    let ga_fft = adjusted_fft(g, size);
+    let fa_adj_fft = fa_fft.clone().adj_fft();
+    let ga_adj_fft = ga_fft.clone().adj_fft();
+    let den_fft = fa_fft
+        .mul_fft(fa_adj_fft.clone())
+        .add_fft(ga_fft.mul_fft(ga_adj_fft.clone()));
+
+    loop {
+        let big_size = big_f.bitsize().max(big_g.bitsize()).max(FLOAT_PRECISION);
+        if big_size < size {
+            break;
+        }
+
+        let num_fft = adjusted_fft(&big_f, big_size)
+            .mul_fft(fa_adj_fft.clone())
+            .add_fft(adjusted_fft(&big_g, big_size).mul_fft(ga_adj_fft.clone()));
+        let k: Polynomial<BigInt> = num_fft
+            .div_fft(den_fft.clone())
+            .ifft()
+            .coefficients
+            .into_iter()
+            .map(|c| BigInt::from(c.re.round() as i64))
+            .collect();
+        if k.coefficients.iter().all(Zero::is_zero) {
+            break;
+        }
+
+        let shift = big_size - size;
+        let shifted = |p: Polynomial<BigInt>| -> Polynomial<BigInt> {
+            p.coefficients.into_iter().map(|c| c << shift).collect()
+        };
+        big_f = big_f.sub(shifted(f.clone().mul(k.clone())));
+        big_g = big_g.sub(shifted(g.clone().mul(k)));
+    }
+
+    (big_f, big_g)
+}
+
+/// Solves the NTRU equation fG - gF = q for F and G
+///
+/// Recurses down the tower of fields through field norms until the equation
+/// is over the integers, then lifts the solution back up, reducing it at each level.
+pub(crate) fn ntru_solve(
+    f: Polynomial<BigInt>,
+    g: Polynomial<BigInt>,
+) -> Option<(Polynomial<BigInt>, Polynomial<BigInt>)> {
+    if let ([f0], [g0]) = (f.coefficients.as_slice(), g.coefficients.as_slice()) {
+        let gcd = f0.extended_gcd(g0);
+        if !gcd.gcd.is_one() {
+            return None;
+        }
+        let q = BigInt::from(Q);
+        let big_f = std::iter::once(-&q * gcd.y).collect();
+        let big_g = std::iter::once(q * gcd.x).collect();
+        return Some((big_f, big_g));
+    }
+
+    let (big_fp, big_gp) = ntru_solve(f.clone().field_norm(), g.clone().field_norm())?;
+    let big_f = big_fp.lift().mul(g.clone().galois_conjugate());
+    let big_g = big_gp.lift().mul(f.clone().galois_conjugate());
+    Some(reduce(&f, &g, big_f, big_g))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    fn random_poly(n: usize, bits: u32, rng: &mut StdRng) -> Polynomial<BigInt> {
+        (0..n)
+            .map(|_| {
+                let high =
+                    BigInt::from(rng.random_range(-1000i64..1000)) << rng.random_range(0..bits);
+                high + rng.random_range(-1000i64..1000)
+            })
+            .collect()
+    }
+
+    fn schoolbook(a: &[BigInt], b: &[BigInt]) -> Vec<BigInt> {
+        let mut ab = vec![BigInt::zero(); a.len() + b.len()];
+        for (i, ai) in a.iter().enumerate() {
+            for (j, bj) in b.iter().enumerate() {
+                ab[i + j] += ai * bj;
+            }
+        }
+        ab
+    }
+
+    #[test]
+    fn karatsuba_matches_schoolbook() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for n in [1, 2, 8, 16, 32, 64, 256] {
+            let a = random_poly(n, 300, &mut rng).coefficients;
+            let b = random_poly(n, 300, &mut rng).coefficients;
+            assert_eq!(karatsuba(&a, &b), schoolbook(&a, &b), "n = {n}");
+        }
+    }
+
+    #[test]
+    fn field_norm_and_lift() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for n in [2, 8, 64, 512] {
+            let a = random_poly(n, 100, &mut rng);
+            let b = random_poly(n / 2, 100, &mut rng);
+
+            // N(a)(x^2) = a(x) * a(-x)
+            let norm = a.clone().field_norm();
+            assert_eq!(norm.len(), n / 2);
+            let product = a.clone().mul(a.clone().galois_conjugate());
+            assert_eq!(norm.lift().coefficients, product.coefficients, "n = {n}");
+
+            // Lifting is a ring homomorphism into Z[x]/(x^n + 1)
+            let lifted = b.clone().lift();
+            assert!(lifted
+                .coefficients
+                .iter()
+                .skip(1)
+                .step_by(2)
+                .all(Zero::is_zero));
+            assert_eq!(
+                b.clone().mul(b.clone()).lift().coefficients,
+                lifted.clone().mul(lifted).coefficients,
+                "n = {n}"
+            );
+        }
+    }
+
+    #[test]
+    fn ntru_solve_solves_ntru_equation() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for n in [512, 1024] {
+            let small = |rng: &mut StdRng| -> Polynomial<BigInt> {
+                (0..n)
+                    .map(|_| BigInt::from(rng.random_range(-4..=4)))
+                    .collect()
+            };
+            let (f, g, big_f, big_g) = loop {
+                let (f, g) = (small(&mut rng), small(&mut rng));
+                if let Some((big_f, big_g)) = ntru_solve(f.clone(), g.clone()) {
+                    break (f, g, big_f, big_g);
+                }
+            };
+            let lhs = f.mul(big_g).sub(g.mul(big_f));
+            let mut q = vec![BigInt::zero(); n];
+            q[0] = BigInt::from(Q);
+            assert_eq!(lhs.coefficients, q, "n = {n}");
+        }
+    }
+}
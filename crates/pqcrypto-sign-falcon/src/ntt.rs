@@ -1,7 +1,19 @@
-use crate::common::{Polynomial, Q};
+#![allow(dead_code)]
+
+use crate::common::Polynomial;
+use crate::simd;
+use crate::zq::Zq;
 
 include!(concat!(env!("OUT_DIR"), "/ntt_constants.rs"));
+include!(concat!(env!("OUT_DIR"), "/ntt_twiddles.rs"));
+
+impl From<&Polynomial<i16>> for Polynomial<Zq> {
+    fn from(value: &Polynomial<i16>) -> Self {
+        value.coefficients.iter().map(|&c| Zq::from(c)).collect()
+    }
+}
 
+#[allow(clippy::upper_case_acronyms)]
 pub trait NTT: Sized {
     fn split_ntt(self) -> (Self, Self);
     fn merge_ntt(self, other: Self) -> Self;
@@ -18,10 +30,30 @@ pub trait NTT: Sized {
     fn div_ntt(self, other: Self) -> Option<Self>;
 }
 
-const I2: u32 = 6145;
-const SQR1: u16 = roots_Zq(2)[0];
+const I2: Zq = Zq::new(6145);
+const SQR1: Zq = Zq::new(roots_Zq(2)[0] as u32);
 
-impl NTT for Polynomial<u32> {
+fn inv_mod_q(x: u16) -> Zq {
+    Zq::new(INV_MOD_Q[x as usize] as u32)
+}
+
+/// Iterative in-place NTT (Cooley-Tukey), leaving the result in bit-reversed order
+pub fn ntt_in_place(a: &mut [Zq]) {
+    simd::ntt(a, &NTT_TWIDDLES);
+}
+
+/// Iterative in-place inverse NTT (Gentleman-Sande), from bit-reversed order
+pub fn intt_in_place(a: &mut [Zq]) {
+    let n = a.len();
+    simd::intt(a, &INTT_TWIDDLES);
+
+    let inv_n = INV_N_MONTGOMERY[n.ilog2() as usize];
+    for x in a {
+        *x = x.mul_montgomery(inv_n);
+    }
+}
+
+impl NTT for Polynomial<Zq> {
     fn split_ntt(self) -> (Self, Self) {
         let n = self.len();
         let w = roots_Zq(n as u16);
@@ -29,9 +61,9 @@ impl NTT for Polynomial<u32> {
             .chunks_exact(2)
             .enumerate()
             .map(|(i, chunks)| {
-                let [even, odd] = chunks else { unreachable!() };
-                let f0_ntt = (I2 * (even + odd)) % Q;
-                let f1_ntt = (I2 * (even - odd) * (INV_MOD_Q[w[2 * i] as usize] as u32)) % Q;
+                let [even, odd] = *chunks else { unreachable!() };
+                let f0_ntt = I2 * (even + odd);
+                let f1_ntt = I2 * (even - odd) * inv_mod_q(w[2 * i]);
                 (f0_ntt, f1_ntt)
             })
             .unzip()
@@ -41,24 +73,24 @@ impl NTT for Polynomial<u32> {
         let n = 2 * self.len();
         let w = roots_Zq(n as u16);
         self.coefficients
-            .iter()
+            .into_iter()
             .enumerate()
             .zip(other.coefficients)
-            .flat_map(|((i, a), b)| [
-                (a + w[2 * i] as u32 * b) % Q,
-                (a - w[2 * i] as u32 * b) % Q
-            ])
+            .flat_map(|((i, a), b)| {
+                let wb = Zq::new(w[2 * i] as u32) * b;
+                [a + wb, a - wb]
+            })
             .collect()
     }
 
     fn ntt(self) -> Self {
-        match self.coefficients.as_slice() {
-            [f0, f1] => Polynomial {
-                coefficients: vec![
-                    (f0 + SQR1 as u32 * f1) % Q,
-                    (f0 - SQR1 as u32 * f1) % Q,
-                ]
-            },
+        match *self.coefficients.as_slice() {
+            [f0, f1] => {
+                let sf1 = SQR1 * f1;
+                Polynomial {
+                    coefficients: vec![f0 + sf1, f0 - sf1],
+                }
+            }
             _ => {
                 let (f0, f1) = self.split();
                 let f0_ntt = f0.ntt();
@@ -69,11 +101,11 @@ impl NTT for Polynomial<u32> {
     }
 
     fn intt(self) -> Self {
-        match self.coefficients.as_slice() {
+        match *self.coefficients.as_slice() {
             [f0_ntt, f1_ntt] => Polynomial {
                 coefficients: vec![
-                    (I2 * (f0_ntt + f1_ntt)) % Q,
-                    (I2 * INV_MOD_Q[SQR1 as usize] as u32 * (f0_ntt - f1_ntt)) % Q,
+                    I2 * (f0_ntt + f1_ntt),
+                    I2 * (f0_ntt - f1_ntt) / SQR1,
                 ]
             },
             _ => {
@@ -91,13 +123,13 @@ impl NTT for Polynomial<u32> {
             other.coefficients.len()
         );
         self.coefficients.into_iter()
-            .zip(other.coefficients.into_iter())
-            .map(|(a, b)| (a + b) % Q)
+            .zip(other.coefficients)
+            .map(|(a, b)| a + b)
             .collect()
     }
 
     fn neg(self) -> Self {
-        self.coefficients.into_iter().map(|a| Q - (a % Q)).collect()
+        self.coefficients.into_iter().map(|a| -a).collect()
     }
 
     fn sub(self, other: Self) -> Self {
@@ -105,15 +137,28 @@ impl NTT for Polynomial<u32> {
     }
 
     fn mul(self, other: Self) -> Self {
-        self.ntt()
-            .mul_ntt(other.ntt())
-            .intt()
+        let mut a = self.coefficients;
+        let mut b = other.coefficients;
+        ntt_in_place(&mut a);
+        ntt_in_place(&mut b);
+        simd::mul_ntt(&mut a, &b);
+        intt_in_place(&mut a);
+        Polynomial { coefficients: a }
     }
 
     fn div(self, other: Self) -> Option<Self> {
-        self.ntt()
-            .div_ntt(other.ntt())
-            .map(|p| p.intt())
+        let mut a = self.coefficients;
+        let mut b = other.coefficients;
+        ntt_in_place(&mut a);
+        ntt_in_place(&mut b);
+        if b.contains(&Zq::ZERO) {
+            return None;
+        }
+        for (x, y) in a.iter_mut().zip(b) {
+            *x = *x * inv_mod_q(y.value() as u16);
+        }
+        intt_in_place(&mut a);
+        Some(Polynomial { coefficients: a })
     }
 
     fn add_ntt(self, other: Self) -> Self {
@@ -124,22 +169,113 @@ impl NTT for Polynomial<u32> {
         self.sub(other)
     }
 
-    fn mul_ntt(self, other: Self) -> Self {
-        self.coefficients.into_iter()
-            .zip(other.coefficients.into_iter())
-            .map(|(a, b)| (a * b) % Q)
-            .collect()
+    fn mul_ntt(mut self, other: Self) -> Self {
+        simd::mul_ntt(&mut self.coefficients, &other.coefficients);
+        self
     }
 
     fn div_ntt(self, other: Self) -> Option<Self> {
-        if other.coefficients.iter().any(|x| *x == 0) {
+        if other.coefficients.contains(&Zq::ZERO) {
             return None;
         }
         let poly = self.coefficients.into_iter()
-            .zip(other.coefficients.into_iter())
-            .map(|(a, b)| (a * INV_MOD_Q[b as usize] as u32) % Q)
+            .zip(other.coefficients)
+            .map(|(a, b)| a * inv_mod_q(b.value() as u16))
             .collect();
 
         Some(poly)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::common::Q;
+
+    fn random_poly(n: usize, rng: &mut StdRng) -> Polynomial<Zq> {
+        (0..n).map(|_| Zq::new(rng.random_range(0..Q))).collect()
+    }
+
+    /// Multiplication in Z_q[x]/(x^n + 1) by definition
+    fn schoolbook_mul(a: &Polynomial<Zq>, b: &Polynomial<Zq>) -> Vec<Zq> {
+        let n = a.len();
+        let mut c = vec![Zq::ZERO; n];
+        for (i, &ai) in a.coefficients.iter().enumerate() {
+            for (j, &bj) in b.coefficients.iter().enumerate() {
+                if i + j < n {
+                    c[i + j] = c[i + j] + ai * bj;
+                } else {
+                    c[i + j - n] = c[i + j - n] - ai * bj;
+                }
+            }
+        }
+        c
+    }
+
+    #[test]
+    fn in_place_mul_matches_schoolbook_and_recursive() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for logn in 1..=10 {
+            let n = 1 << logn;
+            let a = random_poly(n, &mut rng);
+            let b = random_poly(n, &mut rng);
+            let expected = schoolbook_mul(&a, &b);
+            let recursive = a.clone().ntt().mul_ntt(b.clone().ntt()).intt();
+            assert_eq!(recursive.coefficients, expected);
+            assert_eq!(a.mul(b).coefficients, expected);
+        }
+    }
+
+    #[test]
+    fn in_place_ntt_roundtrip() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for logn in 1..=10 {
+            let a = random_poly(1 << logn, &mut rng);
+            let mut b = a.coefficients.clone();
+            ntt_in_place(&mut b);
+            intt_in_place(&mut b);
+            assert_eq!(a.coefficients, b);
+        }
+    }
+
+    #[test]
+    fn in_place_div_inverts_mul() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let a = random_poly(512, &mut rng);
+        let b = random_poly(512, &mut rng);
+        if let Some(c) = a.clone().mul(b.clone()).div(b) {
+            assert_eq!(c.coefficients, a.coefficients);
+        }
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_ntt_mul() {
+        const ITERATIONS: u32 = 1000;
+        let mut rng = StdRng::seed_from_u64(3);
+        for n in [512, 1024] {
+            let a = random_poly(n, &mut rng);
+            let b = random_poly(n, &mut rng);
+
+            let start = Instant::now();
+            for _ in 0..ITERATIONS {
+                std::hint::black_box(a.clone().ntt().mul_ntt(b.clone().ntt()).intt());
+            }
+            let recursive = start.elapsed() / ITERATIONS;
+
+            let start = Instant::now();
+            for _ in 0..ITERATIONS {
+                std::hint::black_box(a.clone().mul(b.clone()));
+            }
+            let in_place = start.elapsed() / ITERATIONS;
+
+            println!("n = {n}: recursive {recursive:?}, in-place {in_place:?} per multiplication");
+        }
+    }
+}
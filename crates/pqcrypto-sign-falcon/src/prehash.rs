@@ -0,0 +1,180 @@
+#![allow(dead_code)]
+
+use std::io;
+
+use sha2::{Digest, Sha256, Sha512};
+use sha3::digest::{ExtendableOutput, Update};
+use sha3::{Sha3_256, Shake128, Shake256};
+
+use crate::common::Absorb;
+
+/// DER encoding of the OID 2.16.840.1.101.3.4.2, without the last arc
+const NIST_HASH_OID_PREFIX: [u8; 10] = [0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02];
+
+/// Hash functions approved for HashFN-DSA
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreHash {
+    Sha256,
+    Sha512,
+    Sha3_256,
+    /// SHAKE128 with a 256-bit output
+    Shake128,
+    /// SHAKE256 with a 512-bit output
+    Shake256,
+}
+
+impl PreHash {
+    /// DER encoding of the OID of the hash function
+    pub fn oid(self) -> [u8; 11] {
+        let arc = match self {
+            Self::Sha256 => 0x01,
+            Self::Sha512 => 0x03,
+            Self::Sha3_256 => 0x08,
+            Self::Shake128 => 0x0B,
+            Self::Shake256 => 0x0C,
+        };
+        let mut oid = [0u8; 11];
+        oid[..10].copy_from_slice(&NIST_HASH_OID_PREFIX);
+        oid[10] = arc;
+        oid
+    }
+
+    pub fn digest_len(self) -> usize {
+        match self {
+            Self::Sha256 | Self::Sha3_256 | Self::Shake128 => 32,
+            Self::Sha512 | Self::Shake256 => 64,
+        }
+    }
+
+    /// Digest of a message held in memory; see `hasher` for a streamed one
+    pub fn digest(self, message: &[u8]) -> Vec<u8> {
+        let mut hasher = self.hasher();
+        hasher.update(message);
+        hasher.finalize()
+    }
+
+    pub fn hasher(self) -> PreHasher {
+        match self {
+            Self::Sha256 => PreHasher::Sha256(Sha256::new()),
+            Self::Sha512 => PreHasher::Sha512(Sha512::new()),
+            Self::Sha3_256 => PreHasher::Sha3_256(Sha3_256::new()),
+            Self::Shake128 => PreHasher::Shake128(Shake128::default()),
+            Self::Shake256 => PreHasher::Shake256(Shake256::default()),
+        }
+    }
+}
+
+/// Incremental digest of a message fed in chunks
+pub enum PreHasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Sha3_256(Sha3_256),
+    Shake128(Shake128),
+    Shake256(Shake256),
+}
+
+impl PreHasher {
+    pub fn hash(&self) -> PreHash {
+        match self {
+            Self::Sha256(_) => PreHash::Sha256,
+            Self::Sha512(_) => PreHash::Sha512,
+            Self::Sha3_256(_) => PreHash::Sha3_256,
+            Self::Shake128(_) => PreHash::Shake128,
+            Self::Shake256(_) => PreHash::Shake256,
+        }
+    }
+
+    pub fn finalize(self) -> Vec<u8> {
+        let mut digest = vec![0u8; self.hash().digest_len()];
+        match self {
+            Self::Sha256(hasher) => digest.copy_from_slice(&hasher.finalize()),
+            Self::Sha512(hasher) => digest.copy_from_slice(&hasher.finalize()),
+            Self::Sha3_256(hasher) => digest.copy_from_slice(&hasher.finalize()),
+            Self::Shake128(hasher) => hasher.finalize_xof_into(&mut digest),
+            Self::Shake256(hasher) => hasher.finalize_xof_into(&mut digest),
+        }
+        digest
+    }
+}
+
+impl Absorb for PreHasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha256(hasher) => Digest::update(hasher, data),
+            Self::Sha512(hasher) => Digest::update(hasher, data),
+            Self::Sha3_256(hasher) => Digest::update(hasher, data),
+            Self::Shake128(hasher) => Update::update(hasher, data),
+            Self::Shake256(hasher) => Update::update(hasher, data),
+        }
+    }
+}
+
+impl io::Write for PreHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASHES: [PreHash; 5] = [
+        PreHash::Sha256,
+        PreHash::Sha512,
+        PreHash::Sha3_256,
+        PreHash::Shake128,
+        PreHash::Shake256,
+    ];
+
+    #[test]
+    fn digests_of_abc() {
+        let digests = HASHES.map(|hash| hash.digest(b"abc"));
+        assert_eq!(
+            digests[0][..8],
+            [0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea]
+        );
+        assert_eq!(
+            digests[1][..8],
+            [0xdd, 0xaf, 0x35, 0xa1, 0x93, 0x61, 0x7a, 0xba]
+        );
+        assert_eq!(
+            digests[2][..8],
+            [0x3a, 0x98, 0x5d, 0xa7, 0x4f, 0xe2, 0x25, 0xb2]
+        );
+        assert_eq!(
+            digests[3][..8],
+            [0x58, 0x81, 0x09, 0x2d, 0xd8, 0x18, 0xbf, 0x5c]
+        );
+        assert_eq!(
+            digests[4][..8],
+            [0x48, 0x33, 0x66, 0x60, 0x13, 0x60, 0xa8, 0x77]
+        );
+        for (hash, digest) in HASHES.iter().zip(digests) {
+            assert_eq!(digest.len(), hash.digest_len(), "{hash:?}");
+        }
+    }
+
+    #[test]
+    fn streamed_digest_matches_digest() {
+        let message: Vec<u8> = (0..1000u32).map(|i| (i * 7) as u8).collect();
+        for hash in HASHES {
+            let mut hasher = hash.hasher();
+            assert_eq!(hasher.hash(), hash);
+            for chunk in message.chunks(77) {
+                hasher.update(chunk);
+            }
+            assert_eq!(hasher.finalize(), hash.digest(&message), "{hash:?}");
+
+            let mut hasher = hash.hasher();
+            let read = hasher.update_reader(&message[..]).unwrap();
+            assert_eq!(read, message.len() as u64);
+            assert_eq!(hasher.finalize(), hash.digest(&message), "{hash:?}");
+        }
+    }
+}
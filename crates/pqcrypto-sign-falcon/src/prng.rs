@@ -0,0 +1,263 @@
+#![allow(dead_code)]
+
+use rand::{CryptoRng, RngCore};
+use sha3::digest::{ExtendableOutput, Update, XofReader};
+use sha3::{Shake256, Shake256Reader};
+
+/// Length of the seed of the ChaCha20 PRNG, drawn from SHAKE256 by the reference
+pub const PRNG_SEED_LEN: usize = 56;
+
+/// ChaCha20 blocks computed per refill, interleaved as in the AVX2 reference code
+const BLOCKS: usize = 8;
+const BUFFER_LEN: usize = BLOCKS * 64;
+
+/// "expand 32-byte k"
+const CW: [u32; 4] = [0x61707865, 0x3320646e, 0x79622d32, 0x6b206574];
+
+/// The PRNG of the Falcon reference implementation, which feeds SamplerZ
+/// during signing
+///
+/// It is ChaCha20 keyed by a 56-byte seed: the first 48 bytes fill the twelve
+/// input words after the constants and the last 8 are a 64-bit block counter,
+/// XORed into the last two words. Each refill runs 8 blocks and interleaves
+/// their output words, so that a portable implementation yields the same
+/// stream as the vectorized one. The blocks are checked against RFC 8439, but
+/// the stream has not been compared with output of the C code.
+pub struct ChaCha20Prng {
+    state: [u32; 12],
+    counter: u64,
+    buffer: [u8; BUFFER_LEN],
+    ptr: usize,
+}
+
+impl ChaCha20Prng {
+    pub fn new(seed: [u8; PRNG_SEED_LEN]) -> Self {
+        let words: Vec<u32> = seed
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        let mut prng = Self {
+            state: words[..12].try_into().unwrap(),
+            counter: words[12] as u64 | (words[13] as u64) << 32,
+            buffer: [0u8; BUFFER_LEN],
+            ptr: 0,
+        };
+        prng.refill();
+        prng
+    }
+
+    /// Seeds the PRNG with the next 56 bytes of `rng`, as `prng_init` does with
+    /// the SHAKE256 context of the reference
+    pub fn from_rng<R: RngCore>(rng: &mut R) -> Self {
+        let mut seed = [0u8; PRNG_SEED_LEN];
+        rng.fill_bytes(&mut seed);
+        Self::new(seed)
+    }
+
+    fn refill(&mut self) {
+        for u in 0..BLOCKS {
+            let mut input = [0u32; 16];
+            input[..4].copy_from_slice(&CW);
+            input[4..].copy_from_slice(&self.state);
+            input[14] ^= self.counter as u32;
+            input[15] ^= (self.counter >> 32) as u32;
+            let output = chacha20_block(input);
+            self.counter = self.counter.wrapping_add(1);
+
+            // Word v of block u lands at byte 32v + 4u
+            for (v, word) in output.iter().enumerate() {
+                let offset = (v << 5) + (u << 2);
+                self.buffer[offset..offset + 4].copy_from_slice(&word.to_le_bytes());
+            }
+        }
+        self.ptr = 0;
+    }
+
+    /// Next 8 bytes, little-endian. The buffer is refilled beforehand when
+    /// fewer than 10 bytes are left, dropping them.
+    pub fn get_u64(&mut self) -> u64 {
+        if self.ptr >= BUFFER_LEN - 9 {
+            self.refill();
+        }
+        let bytes = &self.buffer[self.ptr..self.ptr + 8];
+        self.ptr += 8;
+        u64::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    /// Next byte. The buffer is refilled as soon as it is exhausted.
+    pub fn get_u8(&mut self) -> u8 {
+        let v = self.buffer[self.ptr];
+        self.ptr += 1;
+        if self.ptr == BUFFER_LEN {
+            self.refill();
+        }
+        v
+    }
+}
+
+/// `next_u64` is `get_u64`, which SamplerZ draws through `SamplerSource`; the
+/// other methods read the plain stream of `get_u8`.
+impl RngCore for ChaCha20Prng {
+    fn next_u32(&mut self) -> u32 {
+        let mut buffer = [0u8; 4];
+        self.fill_bytes(&mut buffer);
+        u32::from_le_bytes(buffer)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.get_u64()
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        for byte in dst {
+            *byte = self.get_u8();
+        }
+    }
+}
+
+impl CryptoRng for ChaCha20Prng {}
+
+fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(16);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(12);
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(8);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(7);
+}
+
+/// The ChaCha20 block function: 20 rounds, then the input is added back
+fn chacha20_block(input: [u32; 16]) -> [u32; 16] {
+    let mut x = input;
+    for _ in 0..10 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+    for (x, input) in x.iter_mut().zip(input) {
+        *x = x.wrapping_add(input);
+    }
+    x
+}
+
+/// SHAKE256 over a seed, read as a random generator. This is how the reference
+/// implementation seeds signing (`shake256_init_prng_from_seed`): the salt and
+/// the seed of every `ChaCha20Prng` are read from it in turn.
+pub struct Shake256Rng {
+    reader: Shake256Reader,
+}
+
+impl Shake256Rng {
+    pub fn from_seed(seed: &[u8]) -> Self {
+        let mut shake = Shake256::default();
+        shake.update(seed);
+        Self {
+            reader: shake.finalize_xof(),
+        }
+    }
+}
+
+impl RngCore for Shake256Rng {
+    fn next_u32(&mut self) -> u32 {
+        let mut buffer = [0u8; 4];
+        self.reader.read(&mut buffer);
+        u32::from_le_bytes(buffer)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut buffer = [0u8; 8];
+        self.reader.read(&mut buffer);
+        u64::from_le_bytes(buffer)
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        self.reader.read(dst);
+    }
+}
+
+impl CryptoRng for Shake256Rng {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::samplerz::SamplerSource;
+
+    #[test]
+    fn chacha20_block_rfc8439() {
+        // RFC 8439, section 2.3.2
+        let mut input = [0u32; 16];
+        input[..4].copy_from_slice(&CW);
+        for (i, word) in input[4..12].iter_mut().enumerate() {
+            let i = 4 * i as u32;
+            *word = u32::from_le_bytes([i as u8, i as u8 + 1, i as u8 + 2, i as u8 + 3]);
+        }
+        input[12..].copy_from_slice(&[0x00000001, 0x09000000, 0x4a000000, 0x00000000]);
+        let expected = [
+            0xe4e7f110, 0x15593bd1, 0x1fdd0f50, 0xc47120a3, 0xc7f4d1c7, 0x0368c033, 0x9aaa2204,
+            0x4e6cd4c3, 0x466482d2, 0x09aa9f07, 0x05d7c214, 0xa2028bd9, 0xd19c12b5, 0xb94e16de,
+            0xe883d0cb, 0x4e3c50a2,
+        ];
+        assert_eq!(chacha20_block(input), expected);
+    }
+
+    #[test]
+    fn blocks_are_interleaved() {
+        let seed: [u8; PRNG_SEED_LEN] = core::array::from_fn(|i| i as u8);
+        let prng = ChaCha20Prng::new(seed);
+        let mut input = [0u32; 16];
+        input[..4].copy_from_slice(&CW);
+        for (i, word) in input[4..].iter_mut().enumerate() {
+            *word = u32::from_le_bytes(seed[4 * i..4 * i + 4].try_into().unwrap());
+        }
+        let counter = u64::from_le_bytes(seed[48..].try_into().unwrap());
+        // Block u is keyed with counter + u
+        let blocks: Vec<[u32; 16]> = (0..BLOCKS as u64)
+            .map(|u| {
+                let mut input = input;
+                input[14] ^= (counter + u) as u32;
+                input[15] ^= ((counter + u) >> 32) as u32;
+                chacha20_block(input)
+            })
+            .collect();
+        let stream: Vec<u8> = (0..16)
+            .flat_map(|v| blocks.iter().flat_map(move |block| block[v].to_le_bytes()))
+            .collect();
+        assert_eq!(prng.buffer[..], stream);
+        assert_eq!(prng.counter, counter + BLOCKS as u64);
+    }
+
+    #[test]
+    fn refills_like_the_reference() {
+        let mut prng = ChaCha20Prng::new([7u8; PRNG_SEED_LEN]);
+        let first = prng.buffer;
+
+        // get_u64 drops the last 9 bytes of the buffer
+        for _ in 0..BUFFER_LEN - 9 {
+            prng.get_u8();
+        }
+        let v = prng.get_u64();
+        assert_ne!(prng.buffer, first);
+        assert_eq!(v, u64::from_le_bytes(prng.buffer[..8].try_into().unwrap()));
+        assert_eq!(prng.ptr, 8);
+
+        // The sampler reads get_u64 and get_u8, the other reads are a stream
+        let mut reference = ChaCha20Prng::new([7u8; PRNG_SEED_LEN]);
+        let mut prng = ChaCha20Prng::new([7u8; PRNG_SEED_LEN]);
+        for _ in 0..200 {
+            assert_eq!(SamplerSource::get_u64(&mut prng), reference.get_u64());
+            assert_eq!(SamplerSource::get_u8(&mut prng), reference.get_u8());
+            let mut bytes = [0u8; 13];
+            prng.fill_bytes(&mut bytes);
+            assert_eq!(bytes, [(); 13].map(|_| reference.get_u8()));
+            let v = prng.next_u32();
+            assert_eq!(v.to_le_bytes(), [(); 4].map(|_| reference.get_u8()));
+        }
+    }
+}
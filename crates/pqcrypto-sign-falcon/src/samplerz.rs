@@ -2,48 +2,93 @@
 
 use core::f64;
 use core::f64::consts::LN_2;
-use rand::{CryptoRng, Rng};
+use rand::RngCore;
 
 const ILN_2: f64 = 1f64 / f64::consts::LN_2;
 
-fn base_sampler(random_bytes: [u8; 9]) -> u16 {
-    const RCDT: [u128; 18] = [
-        3024686241123004913666,
-        1564742784480091954050,
-        636254429462080897535,
-        199560484645026482916,
-        47667343854657281903,
-        8595902006365044063,
-        1163297957344668388,
-        117656387352093658,
-        8867391802663976,
-        496969357462633,
-        20680885154299,
-        638331848991,
-        14602316184,
-        247426747,
-        3104126,
-        28824,
-        198,
-        1,
-    ];
-    let mut buffer = [0u8; 16];
-    buffer[7..].copy_from_slice(&random_bytes);
-    // rust implementation uses big endinan. Why?
-    let u = u128::from_le_bytes(buffer);
-    RCDT.iter().filter(|elt| u < **elt).count() as u16
+/// Upper bound on the standard deviations given to the sampler
+const SIGMAX: f64 = 1.8205;
+const INV_2SIGMA2: f64 = 1f64 / ((SIGMAX * SIGMAX) * 2f64);
+
+/// Randomness of the sampler, drawn as in the reference implementation:
+/// 64-bit words and single bytes. Signing and key generation only sample from
+/// a `CryptoRng`.
+pub trait SamplerSource {
+    fn get_u64(&mut self) -> u64;
+    fn get_u8(&mut self) -> u8;
+}
+
+/// `next_u64` and exactly one byte of the stream. `ChaCha20Prng` serves
+/// `next_u64` with the refill rule of the reference `prng_get_u64`.
+impl<R: RngCore> SamplerSource for R {
+    fn get_u64(&mut self) -> u64 {
+        self.next_u64()
+    }
+
+    fn get_u8(&mut self) -> u8 {
+        let mut byte = [0u8];
+        self.fill_bytes(&mut byte);
+        byte[0]
+    }
+}
+
+/// Reverse cumulative distribution table of the half-Gaussian, scaled by 2^72
+const RCDT: [u128; 18] = [
+    3024686241123004913666,
+    1564742784480091954050,
+    636254429462080897535,
+    199560484645026482916,
+    47667343854657281903,
+    8595902006365044063,
+    1163297957344668388,
+    117656387352093658,
+    8867391802663976,
+    496969357462633,
+    20680885154299,
+    638331848991,
+    14602316184,
+    247426747,
+    3104126,
+    28824,
+    198,
+    1,
+];
+
+/// Samples z0 >= 0 from a half-Gaussian of standard deviation SIGMAX, given a
+/// uniform 72-bit integer u
+fn base_sampler(u: u128) -> i32 {
+    RCDT.iter().filter(|elt| u < **elt).count() as i32
 }
 
+/// Constant-time `base_sampler`: every entry is compared, and each comparison
+/// is read from the borrow of a subtraction instead of a branch
+fn base_sampler_ct(u: u128) -> i32 {
+    // u and the entries are below 2^72, so the top bit is set iff u < elt
+    RCDT.iter()
+        .map(|&elt| (u.wrapping_sub(elt) >> 127) as i32)
+        .sum()
+}
+
+/// floor(2^63 * x) for x in [0, 1]. The conversion truncates, which is the
+/// floor for non-negative values; `f64::floor` may be a libm call whose running
+/// time depends on its argument.
 fn compute_z(x: f64) -> u64 {
-    f64::floor(x * (1u64 << 63) as f64) as u64
+    (x * (1u64 << 63) as f64) as u64
+}
+
+/// Branch-free floor, for |x| < 2^31
+fn floor(x: f64) -> i32 {
+    let t = x as i32;
+    t - (x < t as f64) as i32
 }
 
 fn mul_shift(a: u64, b: u64) -> u64 {
     ((a as u128 * b as u128) >> 63) as u64
 }
 
-/// 2^63 * ccs * exp(-x)
-fn approx_exp(x: f64, ccs: f64) -> u64 {
+/// 2^64 * ccs * exp(-x), for x in [0, ln 2) and ccs in (0, 1]. It reaches 2^64
+/// for x = 0 and ccs = 1, hence the wider type.
+fn approx_exp(x: f64, ccs: f64) -> u128 {
     const C: [u64; 13] = [
         0x00000004741183A3,
         0x00000036548CFC06,
@@ -61,59 +106,342 @@ fn approx_exp(x: f64, ccs: f64) -> u64 {
     ];
 
     let [mut y, o @ ..] = C;
-    let mut z = compute_z(x);
-    // wrapping_sub?
+    let z = compute_z(x);
+    // z < 2^63, so z * y >> 63 < y, which never exceeds the next coefficient:
+    // the subtraction cannot underflow
     y = o.iter().fold(y, |acc, elt| elt - mul_shift(z, acc));
-    // rust implementation does not perform shift << 1. Why?
-    z = compute_z(ccs) << 1;
-    mul_shift(z, y)
+    // y ~ 2^63 * exp(-x). The reference computes (floor(2^63 * ccs) << 1) * y >> 63,
+    // scaling the result to 2^64 so that it can be compared with 64 uniform
+    // bits; shifting the product by one bit less is the same without
+    // overflowing for ccs = 1. The C implementation instead shifts the result
+    // of this function, which differs in the lowest bit.
+    (compute_z(ccs) as u128 * y as u128) >> 62
 }
 
-/// Bernoulli distribution
-fn ber_exp(x: f64, ccs: f64, random_bytes: [u8; 7]) -> bool {
-    let mut s = f64::floor(x * ILN_2) as u64;
+/// Returns true with probability ccs * exp(-x), for x >= 0
+fn ber_exp<R>(x: f64, ccs: f64, rng: &mut R) -> bool
+where
+    R: SamplerSource,
+{
+    // Truncation, as x is non-negative
+    let s = (x * ILN_2) as u64;
     let r = x - s as f64 * LN_2;
-    s = s.min(63);
-    // rust implementation performs additional shift << 1 after approx_exp. Why?
-    let z = (approx_exp(r, ccs) - 1) >> s;
-
-    let mut w = None;
-    for (i, p) in (0u16..64u16).rev().step_by(8).zip(random_bytes) {
-        match p as u64 - ((z >> i) & 0xFF) {
-            0 => (),
-            non_zero => {
-                w = Some(non_zero);
-                break;
-            }
+    let s = s.min(63);
+    // approx_exp is at least 2^62 * ccs here, the saturation only guards
+    // against degenerate parameters
+    let z = (approx_exp(r, ccs).saturating_sub(1) >> s) as u64;
+
+    // Lazy comparison of z with a uniform 64-bit integer, most significant
+    // byte first: bytes are drawn only until they differ
+    let mut w = 0;
+    for i in (0..=56).rev().step_by(8) {
+        w = rng.get_u8() as i32 - ((z >> i) & 0xFF) as i32;
+        if w != 0 {
+            break;
         }
     }
+    w < 0
+}
 
-    w.unwrap() > 0
+/// Constant-time `ber_exp`: the comparison always consumes 8 bytes, and
+/// neither the clamping of s nor the comparison branches
+fn ber_exp_ct<R>(x: f64, ccs: f64, rng: &mut R) -> bool
+where
+    R: SamplerSource,
+{
+    let s = (x * ILN_2) as u64;
+    let r = x - s as f64 * LN_2;
+    // min(s, 63): the mask is all ones iff s > 63
+    let s = s ^ ((s ^ 63) & 0u64.wrapping_sub(63u64.wrapping_sub(s) >> 63));
+    let y = approx_exp(r, ccs);
+    let z = ((y - (y != 0) as u128) >> s) as u64;
+
+    // Same outcome as the byte-wise comparison of `ber_exp` on these bytes
+    let v = (0..8).fold(0u64, |v, _| (v << 8) | rng.get_u8() as u64);
+    ((v as u128).wrapping_sub(z as u128) >> 127) == 1
+}
+
+/// Samples an integer from the discrete Gaussian of center mu and standard
+/// deviation sigma, sigmin <= sigma <= SIGMAX
+///
+/// Consumes randomness like the Python code of the specification, which the
+/// known-answer tests rely on, but its running time depends on the random
+/// bytes compared in BerExp. Signing and key generation use `sampler_z_ct`.
+pub fn sampler_z<R>(mu: f64, sigma: f64, sigmin: f64, rng: &mut R) -> i32
+where
+    R: SamplerSource,
+{
+    sample(mu, sigma, sigmin, rng, base_sampler, ber_exp)
 }
 
-fn sampler_z<R>(mu: f64, sigma: f64, sigmin: f64, rng: &mut R) -> u16
+/// Isochronous `sampler_z`: the running time is independent of mu and of the
+/// output. Only the number of rejections varies, and its distribution does not
+/// depend on either.
+pub fn sampler_z_ct<R>(mu: f64, sigma: f64, sigmin: f64, rng: &mut R) -> i32
 where
-    R: Rng + CryptoRng,
+    R: SamplerSource,
 {
-    const SIGMAX: f64 = 1.8205;
-    const INV_2SIGMA2: f64 = 1f64 / ((SIGMAX * SIGMAX) * 2f64);
-    let s = f64::floor(mu);
-    let r = mu - s;
+    sample(mu, sigma, sigmin, rng, base_sampler_ct, ber_exp_ct)
+}
+
+fn sample<R>(
+    mu: f64,
+    sigma: f64,
+    sigmin: f64,
+    rng: &mut R,
+    base_sampler: fn(u128) -> i32,
+    ber_exp: fn(f64, f64, &mut R) -> bool,
+) -> i32
+where
+    R: SamplerSource,
+{
+    let s = floor(mu);
+    let r = mu - s as f64;
     let dss = 1f64 / (2f64 * sigma * sigma);
     let ccs = sigmin / sigma;
 
     loop {
-        // Sampler z0 from a Half-Gaussian
-        let z0 = base_sampler(rng.random());
+        // Sampler z0 from a Half-Gaussian, with the 64 low bits drawn first
+        let lo = rng.get_u64();
+        let hi = rng.get_u8();
+        let z0 = base_sampler((hi as u128) << 64 | lo as u128);
         // Convert z0 into a pseudo-Gaussian sample z
-        let b = (rng.random::<u8>() & 1) as u16;
+        let b = (rng.get_u8() & 1) as i32;
         let z = b + (2 * b - 1) * z0;
         // Rejection sampling to obtain a true Gaussian sample
         let zr = z as f64 - r;
         let z0_f = z0 as f64;
         let x = zr * zr * dss - z0_f * z0_f * INV_2SIGMA2;
-        if ber_exp(x, ccs, rng.random()) {
-            return z + s as u16;
+        if ber_exp(x, ccs, rng) {
+            return z + s;
+        }
+    }
+}
+
+#[cfg(test)]
+mod validation;
+
+#[cfg(test)]
+mod tests {
+    use std::hint::black_box;
+    use std::time::Instant;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    /// Replays a fixed byte string, and fails the test if it is overrun
+    ///
+    /// The vectors of the specification come from its Python implementation,
+    /// which reads the 72 bits of the base sample as 9 big-endian bytes, not
+    /// as the low 64 bits then the high byte: `get_u64` reads all 9 and leaves
+    /// the first one to the following `get_u8`.
+    struct KatBytes<'a> {
+        bytes: &'a [u8],
+        high: Option<u8>,
+    }
+
+    impl<'a> KatBytes<'a> {
+        fn new(bytes: &'a [u8]) -> Self {
+            Self { bytes, high: None }
+        }
+
+        fn take(&mut self, len: usize) -> &'a [u8] {
+            let (head, tail) = self.bytes.split_at(len);
+            self.bytes = tail;
+            head
+        }
+    }
+
+    impl SamplerSource for KatBytes<'_> {
+        fn get_u64(&mut self) -> u64 {
+            let [high, low @ ..] = self.take(9) else {
+                unreachable!()
+            };
+            self.high = Some(*high);
+            u64::from_be_bytes(low.try_into().unwrap())
+        }
+
+        fn get_u8(&mut self) -> u8 {
+            self.high.take().unwrap_or_else(|| self.take(1)[0])
+        }
+    }
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// sigmin of Falcon-512
+    const SIGMIN: f64 = 1.2778336969128337;
+
+    /// SamplerZ test vectors of the Falcon specification: mu, sigma, the random
+    /// bytes consumed and the output
+    ///
+    /// The specification has 16 vectors. The one with mu = -44.36009577368896
+    /// and sigma = 1.7009387219711465 is missing: its random bytes were not at
+    /// hand when this set was written and still have to be copied from the
+    /// specification.
+    const VECTORS: [(f64, f64, &str, i32); 15] = [
+        (-91.90471153063714, 1.7037990414754918, "0fc5442ff043d66e91d1eacac64ea5450a22941edc6c", -92),
+        (-8.322564895434937, 1.7037990414754918, "f4da0f8d8444d1a77265c2ef6f98bbbb4bee7db8d9b3", -8),
+        (-19.096516109216804, 1.7035823083824078, "db47f6d7fb9b19f25c36d6b9334d477a8bc0be68145d", -20),
+        (
+            -11.335543982423326,
+            1.7035823083824078,
+            "ae41b4f5209665c74d00dcc1a8168a7bb516b3190cb42c1ded26cd52aed770eca7dd334e0547bcc3c163ce0b",
+            -12,
+        ),
+        (
+            7.9386734193997555,
+            1.6984647769450156,
+            "31054166c1012780c603ae9b833cec73f2f41ca5807cc89c92158834632f9b1555",
+            8,
+        ),
+        (-28.990850086867255, 1.6984647769450156, "737e9d68a50a06dbbc6477", -30),
+        (-9.071257914091655, 1.6980782114808988, "a98ddd14bf0bf22061d632", -10),
+        (-43.88754568839566, 1.6980782114808988, "3cbf6818a68f7ab9991514", -41),
+        (-58.17435547946095, 1.7010983419195522, "6f8633f5bfa5d26848668e3d5ddd46958e97630410587c", -61),
+        (-43.58664906684732, 1.7010983419195522, "272bc6c25f5c5ee53f83c43a361fbc7cc91dc783e20a", -46),
+        (-34.70565203313315, 1.7009387219711465, "45443c59574c2c3b07e2e1d9071e6d133dbe32754b0a", -34),
+        (-21.783037079346236, 1.6958406126012802, "68163bc1e2cbf3e18e7426", -23),
+        (-39.68827784633828, 1.6958406126012802, "d6a1b51d76222a705a0259", -40),
+        (-18.488607061056847, 1.6955259305261838, "f0523bfaa8a394bf4ea5c10f842366fde286d6a30803", -22),
+        (-48.39610939101591, 1.6955259305261838, "87bd87e63374cee62127fc6931104aab64f136a0485b", -50),
+    ];
+
+    #[test]
+    fn sampler_z_kat() {
+        for (mu, sigma, hex, expected) in VECTORS {
+            let bytes = from_hex(hex);
+            let mut rng = KatBytes::new(&bytes);
+            assert_eq!(
+                sampler_z(mu, sigma, SIGMIN, &mut rng),
+                expected,
+                "mu = {mu}"
+            );
+            assert!(rng.bytes.is_empty(), "mu = {mu}: random bytes left over");
         }
     }
+
+    #[test]
+    fn sampler_z_is_centered() {
+        let mut rng = rand::rng();
+        let samples: Vec<i32> = (0..10000)
+            .map(|_| sampler_z(0., 1.5, SIGMIN, &mut rng))
+            .collect();
+        assert!(samples.iter().any(|&z| z < 0));
+        let mean = samples.iter().sum::<i32>() as f64 / samples.len() as f64;
+        assert!(mean.abs() < 0.1, "mean {mean}");
+    }
+
+    #[test]
+    fn approx_exp_does_not_overflow() {
+        // sigma = sigmin: acceptance is certain for x = 0
+        assert_eq!(approx_exp(0., 1.), 1 << 64);
+        assert_eq!(approx_exp(LN_2 - 1e-9, 1.) >> 62, 2);
+    }
+
+    #[test]
+    fn base_sampler_ct_matches_base_sampler() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let edges = RCDT.iter().flat_map(|&elt| [elt - 1, elt, elt + 1]);
+        let random = (0..10000).map(|_| rng.random::<u128>() >> 56);
+        for u in edges.chain(random) {
+            assert_eq!(base_sampler_ct(u), base_sampler(u), "u = {u}");
+        }
+    }
+
+    #[test]
+    fn ber_exp_ct_matches_ber_exp() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..10000 {
+            let x = rng.random_range(0.0..50.0);
+            let ccs = rng.random_range(0.7..1.0);
+            let mut bytes: [u8; 8] = rng.random();
+            // Make the lazy comparison go deeper than the first byte
+            if rng.random::<bool>() {
+                let s = (x * ILN_2) as u64;
+                let z = ((approx_exp(x - s as f64 * LN_2, ccs) - 1) >> s.min(63)) as u64;
+                bytes[..4].copy_from_slice(&z.to_be_bytes()[..4]);
+            }
+            let lazy = ber_exp(x, ccs, &mut KatBytes::new(&bytes));
+            let ct = ber_exp_ct(x, ccs, &mut KatBytes::new(&bytes));
+            assert_eq!(lazy, ct, "x = {x}, ccs = {ccs}, bytes = {bytes:?}");
+        }
+    }
+
+    /// Welch's t-statistic of two sets of timings, after cropping the slowest
+    /// measurements (interrupts, migrations) as dudect does
+    fn welch_t(timings: &[Vec<f64>; 2]) -> f64 {
+        let mut all: Vec<f64> = timings.concat();
+        all.sort_by(f64::total_cmp);
+        let threshold = all[all.len() * 9 / 10];
+        let [(n0, mean0, var0), (n1, mean1, var1)] = timings.clone().map(|t| {
+            let t: Vec<f64> = t.into_iter().filter(|&x| x < threshold).collect();
+            let n = t.len() as f64;
+            let mean = t.iter().sum::<f64>() / n;
+            let var = t.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.);
+            (n, mean, var)
+        });
+        (mean0 - mean1) / (var0 / n0 + var1 / n1).sqrt()
+    }
+
+    /// Times `run` on inputs of a fixed class (0) and a random class (1),
+    /// interleaved at random, and returns Welch's t-statistic of the timings.
+    /// |t| > 4.5 is evidence that the running time depends on the input.
+    fn dudect<T>(mut input: impl FnMut(usize, &mut StdRng) -> T, mut run: impl FnMut(T)) -> f64 {
+        const MEASUREMENTS: usize = 200_000;
+        const BATCH: usize = 16;
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut timings = [Vec::new(), Vec::new()];
+        for _ in 0..MEASUREMENTS {
+            let class = rng.random::<bool>() as usize;
+            let inputs: Vec<T> = (0..BATCH).map(|_| input(class, &mut rng)).collect();
+            let start = Instant::now();
+            for x in inputs {
+                run(black_box(x));
+            }
+            timings[class].push(start.elapsed().as_nanos() as f64);
+        }
+        welch_t(&timings)
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture dudect`
+    #[test]
+    #[ignore]
+    fn dudect_sampler_z_ct() {
+        let mut rng = StdRng::seed_from_u64(3);
+
+        let t = dudect(
+            |class, rng| [0., rng.random_range(-4096.0..4096.0)][class],
+            |mu| {
+                black_box(sampler_z_ct(mu, 1.7, SIGMIN, &mut rng));
+            },
+        );
+        println!("sampler_z_ct, fixed vs random mu: t = {t:.2}");
+        assert!(t.abs() < 4.5);
+
+        // All ones gives z0 = 0, random bytes the whole range of z0
+        let t = dudect(
+            |class, rng| [(1 << 72) - 1, rng.random::<u128>() >> 56][class],
+            |u| {
+                black_box(base_sampler_ct(u));
+            },
+        );
+        println!("base_sampler_ct, fixed vs random sample: t = {t:.2}");
+        assert!(t.abs() < 4.5);
+
+        let t = dudect(
+            |class, rng| [0.5, rng.random_range(0.0..64.0)][class],
+            |x| {
+                black_box(ber_exp_ct(x, 0.75, &mut rng));
+            },
+        );
+        println!("ber_exp_ct, fixed vs random x: t = {t:.2}");
+        assert!(t.abs() < 4.5);
+    }
 }
@@ -0,0 +1,313 @@
+//! Statistical validation of the discrete Gaussian samplers.
+//!
+//! For each (mu, sigma), samples are checked against the ideal distribution
+//! D(z) ~ exp(-(z - mu)^2 / (2 sigma^2)) with a chi-square and a
+//! Kolmogorov-Smirnov test. The Rényi divergence is computed between the
+//! ideal distribution and the exact output distribution of the sampler,
+//! derived from the RCDT table and `approx_exp`, as divergences of interest
+//! are far below what any sample set can resolve.
+//!
+//! The divergence only covers outputs of ideal probability at least 2^-64.
+//! Further in the tail, the RCDT table is coarse and BerExp clamps its shift
+//! to 63 by design, so the relative error is large but the probabilities are
+//! negligible: there the statistical distance is bounded instead.
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use super::*;
+
+/// Significance level of each test. The seeds are fixed, so failures are
+/// reproducible rather than random.
+const ALPHA: f64 = 1e-3;
+/// Order of the Rényi divergence, 2 * 256 + 1 as in the security analysis
+const RENYI_ORDER: f64 = 513.;
+const RENYI_BOUND: f64 = 1e-9;
+/// Ideal probability below which outputs are part of the tail
+const TAIL_CUT: f64 = 1. / (1u128 << 64) as f64;
+const TAIL_DISTANCE_BOUND: f64 = 1. / (1u128 << 60) as f64;
+
+/// Outputs are floor(mu) + z for z in MIN_Z..=MAX_Z, as z0 <= RCDT.len()
+const MIN_Z: i32 = -(RCDT.len() as i32);
+const MAX_Z: i32 = RCDT.len() as i32 + 1;
+
+const SIGMIN_512: f64 = 1.2778336969128337;
+const SIGMIN_1024: f64 = 1.298280334344292;
+
+type Sampler = fn(f64, f64, f64, &mut StdRng) -> i32;
+
+/// Ideal probabilities of floor(mu) + z, for z in MIN_Z..=MAX_Z
+fn ideal_distribution(mu: f64, sigma: f64) -> Vec<f64> {
+    let r = mu - mu.floor();
+    let weights: Vec<f64> = (MIN_Z..=MAX_Z)
+        .map(|z| (-(z as f64 - r).powi(2) / (2. * sigma * sigma)).exp())
+        .collect();
+    let total: f64 = weights.iter().sum();
+    weights.into_iter().map(|w| w / total).collect()
+}
+
+/// Exact probabilities of floor(mu) + z output by the samplers: the base
+/// sampler draws z0 with the probabilities of the RCDT table, and the proposal
+/// z = b + (2b - 1) z0 is accepted when 64 uniform bits are below the BerExp
+/// threshold
+fn exact_distribution(mu: f64, sigma: f64, sigmin: f64) -> Vec<f64> {
+    let r = mu - mu.floor();
+    let dss = 1. / (2. * sigma * sigma);
+    let ccs = sigmin / sigma;
+    let mut weights = vec![0.; (MAX_Z - MIN_Z + 1) as usize];
+    for z0 in 0..=RCDT.len() {
+        let upper = if z0 == 0 { 1u128 << 72 } else { RCDT[z0 - 1] };
+        let lower = RCDT.get(z0).copied().unwrap_or(0);
+        let base = (upper - lower) as f64 / (1u128 << 72) as f64;
+        for b in 0..2 {
+            let z = b + (2 * b - 1) * z0 as i32;
+            let zr = z as f64 - r;
+            let x = zr * zr * dss - (z0 * z0) as f64 * INV_2SIGMA2;
+            let s = (x * ILN_2) as u64;
+            let threshold = (approx_exp(x - s as f64 * LN_2, ccs) - 1) >> s.min(63);
+            let accept = threshold as f64 / 2f64.powi(64);
+            weights[(z - MIN_Z) as usize] += base * 0.5 * accept;
+        }
+    }
+    let total: f64 = weights.iter().sum();
+    weights.into_iter().map(|w| w / total).collect()
+}
+
+/// Q(a, x), the regularized upper incomplete gamma function
+fn gamma_q(a: f64, x: f64) -> f64 {
+    fn ln_gamma(x: f64) -> f64 {
+        // Lanczos approximation, g = 7
+        const COEFFICIENTS: [f64; 9] = [
+            0.999_999_999_999_809_9,
+            676.520_368_121_885_1,
+            -1_259.139_216_722_402_8,
+            771.323_428_777_653_1,
+            -176.615_029_162_140_6,
+            12.507_343_278_686_905,
+            -0.138_571_095_265_720_12,
+            9.984_369_578_019_572e-6,
+            1.505_632_735_149_311_6e-7,
+        ];
+        let x = x - 1.;
+        let t = x + 7.5;
+        let series = COEFFICIENTS[1..]
+            .iter()
+            .enumerate()
+            .fold(COEFFICIENTS[0], |acc, (i, c)| acc + c / (x + i as f64 + 1.));
+        0.5 * (2. * f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + series.ln()
+    }
+
+    let prefactor = (a * x.ln() - x - ln_gamma(a)).exp();
+    if x < a + 1. {
+        // Series of P(a, x)
+        let (mut term, mut sum, mut n) = (1. / a, 1. / a, a);
+        while term.abs() > sum.abs() * 1e-15 {
+            n += 1.;
+            term *= x / n;
+            sum += term;
+        }
+        1. - sum * prefactor
+    } else {
+        // Continued fraction of Q(a, x), by the modified Lentz method
+        let tiny = 1e-300;
+        let mut b = x + 1. - a;
+        let mut c = 1. / tiny;
+        let mut d = 1. / b;
+        let mut h = d;
+        for i in 1..1000 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.;
+            d = an * d + b;
+            d = if d.abs() < tiny { tiny } else { d };
+            c = b + an / c;
+            c = if c.abs() < tiny { tiny } else { c };
+            d = 1. / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.).abs() < 1e-15 {
+                break;
+            }
+        }
+        prefactor * h
+    }
+}
+
+/// Pearson's statistic and its p-value, pooling the tails so that every bin
+/// expects at least 5 samples
+fn chi_square(counts: &[u64], probabilities: &[f64]) -> (f64, f64) {
+    let n = counts.iter().sum::<u64>() as f64;
+    let mut bins: Vec<(f64, f64)> = Vec::new();
+    let mut pending = (0., 0.);
+    for (&count, &p) in counts.iter().zip(probabilities) {
+        pending = (pending.0 + count as f64, pending.1 + p * n);
+        if pending.1 >= 5. {
+            bins.push(pending);
+            pending = (0., 0.);
+        }
+    }
+    if let Some(last) = bins.last_mut() {
+        *last = (last.0 + pending.0, last.1 + pending.1);
+    }
+    let statistic: f64 = bins.iter().map(|(o, e)| (o - e).powi(2) / e).sum();
+    let df = (bins.len() - 1) as f64;
+    (statistic, gamma_q(df / 2., statistic / 2.))
+}
+
+/// Largest distance between the empirical and the ideal cumulative
+/// distribution functions, and its critical value at ALPHA (conservative for
+/// discrete distributions)
+fn kolmogorov_smirnov(counts: &[u64], probabilities: &[f64]) -> (f64, f64) {
+    let n = counts.iter().sum::<u64>() as f64;
+    let (mut empirical, mut ideal, mut d) = (0., 0., 0f64);
+    for (&count, &p) in counts.iter().zip(probabilities) {
+        empirical += count as f64 / n;
+        ideal += p;
+        d = d.max((empirical - ideal).abs());
+    }
+    let critical = (-(ALPHA / 2.).ln() / 2.).sqrt() / n.sqrt();
+    (d, critical)
+}
+
+/// R_a(p || q) - 1 over the outputs outside the tail, computed so that
+/// divergences close to 1 keep their precision, and the statistical distance
+/// over the tail
+fn renyi_divergence(p: &[f64], q: &[f64], a: f64) -> (f64, f64) {
+    let (body, tail): (Vec<_>, Vec<_>) = p.iter().zip(q).partition(|(_, &q)| q >= TAIL_CUT);
+    let s_minus_1: f64 = body
+        .iter()
+        .filter(|(&p, _)| p > 0.)
+        .map(|(&p, &q)| p * ((a - 1.) * (p / q).ln()).exp_m1())
+        .sum::<f64>()
+        + (body.iter().map(|(&p, _)| p).sum::<f64>() - 1.);
+    let tail_distance = tail.iter().map(|(&p, &q)| (p - q).abs()).sum::<f64>() / 2.;
+    ((s_minus_1.ln_1p() / (a - 1.)).exp_m1(), tail_distance)
+}
+
+struct Report {
+    mu: f64,
+    sigma: f64,
+    chi_square: (f64, f64),
+    kolmogorov_smirnov: (f64, f64),
+    /// Rényi divergence minus 1 and statistical distance over the tail
+    renyi: (f64, f64),
+}
+
+impl Report {
+    fn passed(&self) -> bool {
+        self.chi_square.1 >= ALPHA
+            && self.kolmogorov_smirnov.0 <= self.kolmogorov_smirnov.1
+            && self.renyi.0 <= RENYI_BOUND
+            && self.renyi.1 <= TAIL_DISTANCE_BOUND
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "mu = {:>10.4}, sigma = {:.4}: chi2 = {:>7.2} (p = {:.4}), KS = {:.5} (max {:.5}), R_{} - 1 = {:.2e}, tail SD = {:.2e} => {}",
+            self.mu,
+            self.sigma,
+            self.chi_square.0,
+            self.chi_square.1,
+            self.kolmogorov_smirnov.0,
+            self.kolmogorov_smirnov.1,
+            RENYI_ORDER,
+            self.renyi.0,
+            self.renyi.1,
+            if self.passed() { "pass" } else { "FAIL" },
+        )
+    }
+}
+
+fn validate(
+    sampler: Sampler,
+    mu: f64,
+    sigma: f64,
+    sigmin: f64,
+    samples: usize,
+    seed: u64,
+) -> Report {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let s = mu.floor() as i32;
+    let mut counts = vec![0u64; (MAX_Z - MIN_Z + 1) as usize];
+    for _ in 0..samples {
+        let z = sampler(mu, sigma, sigmin, &mut rng) - s;
+        assert!(
+            (MIN_Z..=MAX_Z).contains(&z),
+            "sample {z} outside the support"
+        );
+        counts[(z - MIN_Z) as usize] += 1;
+    }
+    let ideal = ideal_distribution(mu, sigma);
+    Report {
+        mu,
+        sigma,
+        chi_square: chi_square(&counts, &ideal),
+        kolmogorov_smirnov: kolmogorov_smirnov(&counts, &ideal),
+        renyi: renyi_divergence(&exact_distribution(mu, sigma, sigmin), &ideal, RENYI_ORDER),
+    }
+}
+
+/// Validates both samplers on every (mu, sigma), printing a report per pair
+fn run(mus: &[f64], sigmas: &[(f64, f64)], samples: usize) {
+    let samplers: [(&str, Sampler); 2] = [("sampler_z", sampler_z), ("sampler_z_ct", sampler_z_ct)];
+    let mut failures = 0;
+    for (name, sampler) in samplers {
+        for (i, &mu) in mus.iter().enumerate() {
+            for (j, &(sigma, sigmin)) in sigmas.iter().enumerate() {
+                let seed = (i * sigmas.len() + j) as u64;
+                let report = validate(sampler, mu, sigma, sigmin, samples, seed);
+                println!("{name}: {report}");
+                failures += !report.passed() as usize;
+            }
+        }
+    }
+    assert_eq!(failures, 0, "{failures} distributions failed validation");
+}
+
+#[test]
+fn chi_square_p_values() {
+    // Upper tail probabilities of the chi-square distribution from tables
+    for (df, x, p) in [(1., 3.841, 0.05), (10., 23.209, 0.01), (37., 69.346, 0.001)] {
+        let q = gamma_q(df / 2., x / 2.);
+        assert!((q - p).abs() < 1e-3 * p, "df = {df}: {q} != {p}");
+    }
+}
+
+#[test]
+fn samplers_follow_discrete_gaussian() {
+    run(
+        &[0., 0.5, -17.25],
+        &[(SIGMIN_512, SIGMIN_512), (1.8205, SIGMIN_1024)],
+        100_000,
+    );
+}
+
+/// Large sample sets over many centers and deviations. Run before releasing
+/// sampler changes with `cargo test --release -- --ignored --nocapture validation`
+#[test]
+#[ignore]
+fn validation_full() {
+    let mus = [
+        0.,
+        0.1,
+        0.25,
+        0.5,
+        0.75,
+        0.999,
+        -1.5,
+        -91.90471153063714,
+        7.9386734193997555,
+        1234.5678,
+    ];
+    let sigmas = [
+        (SIGMIN_512, SIGMIN_512),
+        (1.5, SIGMIN_512),
+        (1.7037990414754918, SIGMIN_512),
+        (SIGMIN_1024, SIGMIN_1024),
+        (1.6, SIGMIN_1024),
+        (1.8205, SIGMIN_1024),
+    ];
+    run(&mus, &sigmas, 2_000_000);
+}
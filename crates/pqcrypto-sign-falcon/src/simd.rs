@@ -0,0 +1,836 @@
+#![allow(dead_code)]
+
+//! Vectorised kernels for the FFT and NTT hot loops.
+//!
+//! Every kernel has a portable implementation and, where available, an AVX2
+//! (x86_64) or NEON (aarch64) one selected at runtime. The transforms are
+//! dispatched as a whole, so that the feature check is paid once per transform
+//! rather than once per block of butterflies. The vector kernels
+//! perform exactly the same floating-point operations in the same order as the
+//! portable ones, so all paths return bit-identical results.
+//!
+//! FMA is deliberately not used, although AVX2/FMA kernels were asked for: a
+//! fused multiply-add rounds once instead of twice, so FMA kernels would give
+//! other signatures than the portable code for the same randomness.
+
+use num_complex::Complex64;
+
+use crate::common::Q;
+use crate::zq::{Zq, Q0I};
+
+/// 2^32 mod q, the Montgomery form of R = 2^16
+const R2: u32 = ((1u64 << 32) % Q as u64) as u32;
+
+#[cfg(target_arch = "x86_64")]
+fn avx2_available() -> bool {
+    is_x86_feature_detected!("avx2")
+}
+
+#[cfg(target_arch = "aarch64")]
+fn neon_available() -> bool {
+    std::arch::is_aarch64_feature_detected!("neon")
+}
+
+/// Calls the fastest implementation of a kernel supported by the running CPU
+macro_rules! dispatch {
+    ($kernel:ident($($arg:expr),*)) => {{
+        #[cfg(target_arch = "x86_64")]
+        if avx2_available() {
+            // SAFETY: the required CPU features were detected at runtime
+            return unsafe { avx2::$kernel($($arg),*) };
+        }
+        #[cfg(target_arch = "aarch64")]
+        if neon_available() {
+            // SAFETY: the required CPU features were detected at runtime
+            return unsafe { neon::$kernel($($arg),*) };
+        }
+        portable::$kernel($($arg),*)
+    }};
+}
+
+/// Cooley-Tukey layers over blocks of n, n/2, ..., 2 elements: block i of the
+/// layer with m blocks goes through the butterflies with twiddles[m + i]
+#[inline(always)]
+fn forward_layers<T, S: Copy>(
+    a: &mut [T],
+    twiddles: &[S],
+    mut butterflies: impl FnMut(&mut [T], &mut [T], S),
+) {
+    let n = a.len();
+    let mut t = n;
+    let mut m = 1;
+    while m < n {
+        let ht = t / 2;
+        for (i, block) in a.chunks_exact_mut(t).enumerate() {
+            let (lo, hi) = block.split_at_mut(ht);
+            butterflies(lo, hi, twiddles[m + i]);
+        }
+        t = ht;
+        m *= 2;
+    }
+}
+
+/// Gentleman-Sande layers, undoing `forward_layers` up to a factor n
+#[inline(always)]
+fn inverse_layers<T, S: Copy>(
+    a: &mut [T],
+    twiddles: &[S],
+    mut butterflies: impl FnMut(&mut [T], &mut [T], S),
+) {
+    let n = a.len();
+    let mut t = 1;
+    let mut m = n;
+    while m > 1 {
+        let hm = m / 2;
+        for (i, block) in a.chunks_exact_mut(2 * t).enumerate() {
+            let (lo, hi) = block.split_at_mut(t);
+            butterflies(lo, hi, twiddles[hm + i]);
+        }
+        t *= 2;
+        m = hm;
+    }
+}
+
+/// FFT layers of butterflies (u, v) <- (u + s * v, u - s * v), without the reordering
+pub fn fft(a: &mut [Complex64], twiddles: &[Complex64]) {
+    dispatch!(fft(a, twiddles))
+}
+
+/// Inverse FFT layers of butterflies (u, v) <- (u + v, (u - v) * s) with the
+/// conjugate twiddles, which are their inverses on the unit circle, without
+/// the division by n
+pub fn ifft(a: &mut [Complex64], twiddles: &[Complex64]) {
+    dispatch!(ifft(a, twiddles))
+}
+
+/// NTT layers of butterflies (u, v) <- (u + s * v, u - s * v), twiddles in
+/// Montgomery form
+pub fn ntt(a: &mut [Zq], twiddles: &[u16]) {
+    dispatch!(ntt(a, twiddles))
+}
+
+/// Inverse NTT layers of butterflies (u, v) <- (u + v, (u - v) * s), without the
+/// division by n
+pub fn intt(a: &mut [Zq], twiddles: &[u16]) {
+    dispatch!(intt(a, twiddles))
+}
+
+/// Pointwise product a <- a * b in FFT representation
+pub fn mul_fft(a: &mut [Complex64], b: &[Complex64]) {
+    dispatch!(mul_fft(a, b))
+}
+
+/// Splits f in FFT representation into f0 and f1, w being roots(len(f))
+pub fn split_fft(f: &[Complex64], w: &[Complex64], f0: &mut [Complex64], f1: &mut [Complex64]) {
+    dispatch!(split_fft(f, w, f0, f1))
+}
+
+/// Merges f0 and f1 in FFT representation into f, w being roots(len(f))
+pub fn merge_fft(f0: &[Complex64], f1: &[Complex64], w: &[Complex64], f: &mut [Complex64]) {
+    dispatch!(merge_fft(f0, f1, w, f))
+}
+
+/// Pointwise product a <- a * b in NTT representation
+pub fn mul_ntt(a: &mut [Zq], b: &[Zq]) {
+    dispatch!(mul_ntt(a, b))
+}
+
+mod portable {
+    use super::*;
+
+    pub fn fft(a: &mut [Complex64], twiddles: &[Complex64]) {
+        forward_layers(a, twiddles, fft_butterflies)
+    }
+
+    pub fn ifft(a: &mut [Complex64], twiddles: &[Complex64]) {
+        inverse_layers(a, twiddles, |lo, hi, s: Complex64| {
+            ifft_butterflies(lo, hi, s.conj())
+        })
+    }
+
+    pub fn ntt(a: &mut [Zq], twiddles: &[u16]) {
+        forward_layers(a, twiddles, ntt_butterflies)
+    }
+
+    pub fn intt(a: &mut [Zq], twiddles: &[u16]) {
+        inverse_layers(a, twiddles, intt_butterflies)
+    }
+
+    pub fn fft_butterflies(lo: &mut [Complex64], hi: &mut [Complex64], s: Complex64) {
+        for (u, v) in lo.iter_mut().zip(hi) {
+            let w = *v * s;
+            (*u, *v) = (*u + w, *u - w);
+        }
+    }
+
+    pub fn ifft_butterflies(lo: &mut [Complex64], hi: &mut [Complex64], s: Complex64) {
+        for (u, v) in lo.iter_mut().zip(hi) {
+            (*u, *v) = (*u + *v, (*u - *v) * s);
+        }
+    }
+
+    pub fn mul_fft(a: &mut [Complex64], b: &[Complex64]) {
+        for (x, y) in a.iter_mut().zip(b) {
+            *x *= y;
+        }
+    }
+
+    pub fn split_fft(f: &[Complex64], w: &[Complex64], f0: &mut [Complex64], f1: &mut [Complex64]) {
+        for (i, ((chunk, x0), x1)) in f.chunks_exact(2).zip(f0).zip(f1).enumerate() {
+            let [even, odd] = chunk else { unreachable!() };
+            *x0 = (even + odd) * 0.5;
+            *x1 = (even - odd) * 0.5 * w[2 * i].conj();
+        }
+    }
+
+    pub fn merge_fft(f0: &[Complex64], f1: &[Complex64], w: &[Complex64], f: &mut [Complex64]) {
+        for (i, ((a, b), chunk)) in f0.iter().zip(f1).zip(f.chunks_exact_mut(2)).enumerate() {
+            let wb = w[2 * i] * b;
+            chunk[0] = a + wb;
+            chunk[1] = a - wb;
+        }
+    }
+
+    pub fn ntt_butterflies(lo: &mut [Zq], hi: &mut [Zq], s: u16) {
+        for (u, v) in lo.iter_mut().zip(hi) {
+            let w = v.mul_montgomery(s);
+            (*u, *v) = (*u + w, *u - w);
+        }
+    }
+
+    pub fn intt_butterflies(lo: &mut [Zq], hi: &mut [Zq], s: u16) {
+        for (u, v) in lo.iter_mut().zip(hi) {
+            (*u, *v) = (*u + *v, (*u - *v).mul_montgomery(s));
+        }
+    }
+
+    pub fn mul_ntt(a: &mut [Zq], b: &[Zq]) {
+        for (x, &y) in a.iter_mut().zip(b) {
+            *x = *x * y;
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    use super::*;
+
+    /// Products of the complex numbers packed as [re, im, re, im], computed
+    /// like `Complex64::mul`
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    fn cmul(a: __m256d, b: __m256d) -> __m256d {
+        let b_re = _mm256_movedup_pd(b);
+        let b_im = _mm256_permute_pd::<0b1111>(b);
+        let a_swap = _mm256_permute_pd::<0b0101>(a);
+        _mm256_addsub_pd(_mm256_mul_pd(a, b_re), _mm256_mul_pd(a_swap, b_im))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn load(p: *const Complex64) -> __m256d {
+        _mm256_loadu_pd(p as *const f64)
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn store(p: *mut Complex64, x: __m256d) {
+        _mm256_storeu_pd(p as *mut f64, x)
+    }
+
+    /// Packs w[0] and w[2], the twiddles of two consecutive split/merge steps
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn load_twiddles(w: &[Complex64]) -> __m256d {
+        let lo = _mm_loadu_pd(&w[0] as *const Complex64 as *const f64);
+        let hi = _mm_loadu_pd(&w[2] as *const Complex64 as *const f64);
+        _mm256_set_m128d(hi, lo)
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn fft(a: &mut [Complex64], twiddles: &[Complex64]) {
+        forward_layers(a, twiddles, |lo, hi, s| fft_butterflies(lo, hi, s))
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn ifft(a: &mut [Complex64], twiddles: &[Complex64]) {
+        inverse_layers(a, twiddles, |lo, hi, s: Complex64| {
+            ifft_butterflies(lo, hi, s.conj())
+        })
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn fft_butterflies(lo: &mut [Complex64], hi: &mut [Complex64], s: Complex64) {
+        let sv = _mm256_setr_pd(s.re, s.im, s.re, s.im);
+        let mut lo_chunks = lo.chunks_exact_mut(2);
+        let mut hi_chunks = hi.chunks_exact_mut(2);
+        for (u, v) in (&mut lo_chunks).zip(&mut hi_chunks) {
+            let a = load(u.as_ptr());
+            let w = cmul(load(v.as_ptr()), sv);
+            store(u.as_mut_ptr(), _mm256_add_pd(a, w));
+            store(v.as_mut_ptr(), _mm256_sub_pd(a, w));
+        }
+        portable::fft_butterflies(lo_chunks.into_remainder(), hi_chunks.into_remainder(), s);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn ifft_butterflies(lo: &mut [Complex64], hi: &mut [Complex64], s: Complex64) {
+        let sv = _mm256_setr_pd(s.re, s.im, s.re, s.im);
+        let mut lo_chunks = lo.chunks_exact_mut(2);
+        let mut hi_chunks = hi.chunks_exact_mut(2);
+        for (u, v) in (&mut lo_chunks).zip(&mut hi_chunks) {
+            let a = load(u.as_ptr());
+            let b = load(v.as_ptr());
+            store(u.as_mut_ptr(), _mm256_add_pd(a, b));
+            store(v.as_mut_ptr(), cmul(_mm256_sub_pd(a, b), sv));
+        }
+        portable::ifft_butterflies(lo_chunks.into_remainder(), hi_chunks.into_remainder(), s);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn mul_fft(a: &mut [Complex64], b: &[Complex64]) {
+        let mut a_chunks = a.chunks_exact_mut(2);
+        let mut b_chunks = b.chunks_exact(2);
+        for (x, y) in (&mut a_chunks).zip(&mut b_chunks) {
+            store(x.as_mut_ptr(), cmul(load(x.as_ptr()), load(y.as_ptr())));
+        }
+        portable::mul_fft(a_chunks.into_remainder(), b_chunks.remainder());
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn split_fft(
+        f: &[Complex64],
+        w: &[Complex64],
+        f0: &mut [Complex64],
+        f1: &mut [Complex64],
+    ) {
+        let half = _mm256_set1_pd(0.5);
+        let conj = _mm256_setr_pd(0., -0., 0., -0.);
+        let pairs = f0.len() / 2;
+        for i in (0..2 * pairs).step_by(2) {
+            let v0 = load(&f[2 * i]);
+            let v1 = load(&f[2 * i + 2]);
+            let even = _mm256_permute2f128_pd::<0x20>(v0, v1);
+            let odd = _mm256_permute2f128_pd::<0x31>(v0, v1);
+            let w_conj = _mm256_xor_pd(load_twiddles(&w[2 * i..]), conj);
+            store(&mut f0[i], _mm256_mul_pd(_mm256_add_pd(even, odd), half));
+            store(
+                &mut f1[i],
+                cmul(_mm256_mul_pd(_mm256_sub_pd(even, odd), half), w_conj),
+            );
+        }
+        let i = 2 * pairs;
+        portable::split_fft(&f[2 * i..], &w[2 * i..], &mut f0[i..], &mut f1[i..]);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn merge_fft(
+        f0: &[Complex64],
+        f1: &[Complex64],
+        w: &[Complex64],
+        f: &mut [Complex64],
+    ) {
+        let pairs = f0.len() / 2;
+        for i in (0..2 * pairs).step_by(2) {
+            let a = load(&f0[i]);
+            let wb = cmul(load_twiddles(&w[2 * i..]), load(&f1[i]));
+            let plus = _mm256_add_pd(a, wb);
+            let minus = _mm256_sub_pd(a, wb);
+            store(&mut f[2 * i], _mm256_permute2f128_pd::<0x20>(plus, minus));
+            store(
+                &mut f[2 * i + 2],
+                _mm256_permute2f128_pd::<0x31>(plus, minus),
+            );
+        }
+        let i = 2 * pairs;
+        portable::merge_fft(&f0[i..], &f1[i..], &w[2 * i..], &mut f[2 * i..]);
+    }
+
+    /// Widens eight canonical field elements to 32-bit lanes
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn load_zq(p: *const Zq) -> __m256i {
+        _mm256_cvtepu16_epi32(_mm_loadu_si128(p as *const __m128i))
+    }
+
+    /// Narrows eight lanes in [0, q) back to field elements
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn store_zq(p: *mut Zq, x: __m256i) {
+        let packed = _mm256_permute4x64_epi64::<0b1000>(_mm256_packus_epi32(x, x));
+        _mm_storeu_si128(p as *mut __m128i, _mm256_castsi256_si128(packed));
+    }
+
+    /// Maps [0, 2q) to [0, q): x - q wraps around whenever x < q
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    fn reduce(x: __m256i) -> __m256i {
+        _mm256_min_epu32(x, _mm256_sub_epi32(x, _mm256_set1_epi32(Q as i32)))
+    }
+
+    /// Lane-wise `Zq::mul_montgomery`
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    fn mul_montgomery(x: __m256i, y: __m256i) -> __m256i {
+        let z = _mm256_mullo_epi32(x, y);
+        let m = _mm256_and_si256(
+            _mm256_mullo_epi32(z, _mm256_set1_epi32(Q0I as i32)),
+            _mm256_set1_epi32(0xFFFF),
+        );
+        let w = _mm256_mullo_epi32(m, _mm256_set1_epi32(Q as i32));
+        reduce(_mm256_srli_epi32::<16>(_mm256_add_epi32(z, w)))
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn ntt(a: &mut [Zq], twiddles: &[u16]) {
+        forward_layers(a, twiddles, |lo, hi, s| ntt_butterflies(lo, hi, s))
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn intt(a: &mut [Zq], twiddles: &[u16]) {
+        inverse_layers(a, twiddles, |lo, hi, s| intt_butterflies(lo, hi, s))
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn ntt_butterflies(lo: &mut [Zq], hi: &mut [Zq], s: u16) {
+        let sv = _mm256_set1_epi32(s as i32);
+        let q = _mm256_set1_epi32(Q as i32);
+        let mut lo_chunks = lo.chunks_exact_mut(8);
+        let mut hi_chunks = hi.chunks_exact_mut(8);
+        for (u, v) in (&mut lo_chunks).zip(&mut hi_chunks) {
+            let a = load_zq(u.as_ptr());
+            let w = mul_montgomery(load_zq(v.as_ptr()), sv);
+            store_zq(u.as_mut_ptr(), reduce(_mm256_add_epi32(a, w)));
+            store_zq(
+                v.as_mut_ptr(),
+                reduce(_mm256_sub_epi32(_mm256_add_epi32(a, q), w)),
+            );
+        }
+        portable::ntt_butterflies(lo_chunks.into_remainder(), hi_chunks.into_remainder(), s);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn intt_butterflies(lo: &mut [Zq], hi: &mut [Zq], s: u16) {
+        let sv = _mm256_set1_epi32(s as i32);
+        let q = _mm256_set1_epi32(Q as i32);
+        let mut lo_chunks = lo.chunks_exact_mut(8);
+        let mut hi_chunks = hi.chunks_exact_mut(8);
+        for (u, v) in (&mut lo_chunks).zip(&mut hi_chunks) {
+            let a = load_zq(u.as_ptr());
+            let b = load_zq(v.as_ptr());
+            let d = reduce(_mm256_sub_epi32(_mm256_add_epi32(a, q), b));
+            store_zq(u.as_mut_ptr(), reduce(_mm256_add_epi32(a, b)));
+            store_zq(v.as_mut_ptr(), mul_montgomery(d, sv));
+        }
+        portable::intt_butterflies(lo_chunks.into_remainder(), hi_chunks.into_remainder(), s);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn mul_ntt(a: &mut [Zq], b: &[Zq]) {
+        let r2 = _mm256_set1_epi32(R2 as i32);
+        let mut a_chunks = a.chunks_exact_mut(8);
+        let mut b_chunks = b.chunks_exact(8);
+        for (x, y) in (&mut a_chunks).zip(&mut b_chunks) {
+            // x * y / R, then * R^2 / R
+            let xy = mul_montgomery(load_zq(x.as_ptr()), load_zq(y.as_ptr()));
+            store_zq(x.as_mut_ptr(), mul_montgomery(xy, r2));
+        }
+        portable::mul_ntt(a_chunks.into_remainder(), b_chunks.remainder());
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use std::arch::aarch64::*;
+
+    use super::*;
+
+    /// Product of single complex numbers packed as [re, im], computed like
+    /// `Complex64::mul`
+    #[inline]
+    #[target_feature(enable = "neon")]
+    unsafe fn cmul(a: float64x2_t, b: float64x2_t) -> float64x2_t {
+        const NEGATE_RE: [f64; 2] = [-1., 1.];
+        let b_re = vdupq_laneq_f64::<0>(b);
+        let b_im = vdupq_laneq_f64::<1>(b);
+        let a_swap = vextq_f64::<1>(a, a);
+        let cross = vmulq_f64(vmulq_f64(a_swap, b_im), vld1q_f64(NEGATE_RE.as_ptr()));
+        vaddq_f64(vmulq_f64(a, b_re), cross)
+    }
+
+    #[inline]
+    #[target_feature(enable = "neon")]
+    unsafe fn load(p: *const Complex64) -> float64x2_t {
+        vld1q_f64(p as *const f64)
+    }
+
+    #[inline]
+    #[target_feature(enable = "neon")]
+    unsafe fn store(p: *mut Complex64, x: float64x2_t) {
+        vst1q_f64(p as *mut f64, x)
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn fft(a: &mut [Complex64], twiddles: &[Complex64]) {
+        forward_layers(a, twiddles, |lo, hi, s| fft_butterflies(lo, hi, s))
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn ifft(a: &mut [Complex64], twiddles: &[Complex64]) {
+        inverse_layers(a, twiddles, |lo, hi, s: Complex64| {
+            ifft_butterflies(lo, hi, s.conj())
+        })
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn fft_butterflies(lo: &mut [Complex64], hi: &mut [Complex64], s: Complex64) {
+        let sv = load(&s);
+        for (u, v) in lo.iter_mut().zip(hi) {
+            let a = load(u);
+            let w = cmul(load(v), sv);
+            store(u, vaddq_f64(a, w));
+            store(v, vsubq_f64(a, w));
+        }
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn ifft_butterflies(lo: &mut [Complex64], hi: &mut [Complex64], s: Complex64) {
+        let sv = load(&s);
+        for (u, v) in lo.iter_mut().zip(hi) {
+            let a = load(u);
+            let b = load(v);
+            store(u, vaddq_f64(a, b));
+            store(v, cmul(vsubq_f64(a, b), sv));
+        }
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn mul_fft(a: &mut [Complex64], b: &[Complex64]) {
+        for (x, y) in a.iter_mut().zip(b) {
+            store(x, cmul(load(x), load(y)));
+        }
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn split_fft(
+        f: &[Complex64],
+        w: &[Complex64],
+        f0: &mut [Complex64],
+        f1: &mut [Complex64],
+    ) {
+        const CONJ: [f64; 2] = [1., -1.];
+        let half = vdupq_n_f64(0.5);
+        let conj = vld1q_f64(CONJ.as_ptr());
+        for (i, ((chunk, x0), x1)) in f.chunks_exact(2).zip(f0).zip(f1).enumerate() {
+            let even = load(&chunk[0]);
+            let odd = load(&chunk[1]);
+            let w_conj = vmulq_f64(load(&w[2 * i]), conj);
+            store(x0, vmulq_f64(vaddq_f64(even, odd), half));
+            store(x1, cmul(vmulq_f64(vsubq_f64(even, odd), half), w_conj));
+        }
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn merge_fft(
+        f0: &[Complex64],
+        f1: &[Complex64],
+        w: &[Complex64],
+        f: &mut [Complex64],
+    ) {
+        for (i, ((a, b), chunk)) in f0.iter().zip(f1).zip(f.chunks_exact_mut(2)).enumerate() {
+            let a = load(a);
+            let wb = cmul(load(&w[2 * i]), load(b));
+            store(&mut chunk[0], vaddq_f64(a, wb));
+            store(&mut chunk[1], vsubq_f64(a, wb));
+        }
+    }
+
+    /// Widens four canonical field elements to 32-bit lanes
+    #[inline]
+    #[target_feature(enable = "neon")]
+    unsafe fn load_zq(p: *const Zq) -> uint32x4_t {
+        vmovl_u16(vld1_u16(p as *const u16))
+    }
+
+    /// Narrows four lanes in [0, q) back to field elements
+    #[inline]
+    #[target_feature(enable = "neon")]
+    unsafe fn store_zq(p: *mut Zq, x: uint32x4_t) {
+        vst1_u16(p as *mut u16, vmovn_u32(x))
+    }
+
+    /// Maps [0, 2q) to [0, q): x - q wraps around whenever x < q
+    #[inline]
+    #[target_feature(enable = "neon")]
+    unsafe fn reduce(x: uint32x4_t) -> uint32x4_t {
+        vminq_u32(x, vsubq_u32(x, vdupq_n_u32(Q)))
+    }
+
+    /// Lane-wise `Zq::mul_montgomery`
+    #[inline]
+    #[target_feature(enable = "neon")]
+    unsafe fn mul_montgomery(x: uint32x4_t, y: uint32x4_t) -> uint32x4_t {
+        let z = vmulq_u32(x, y);
+        let m = vandq_u32(vmulq_u32(z, vdupq_n_u32(Q0I)), vdupq_n_u32(0xFFFF));
+        let w = vmulq_u32(m, vdupq_n_u32(Q));
+        reduce(vshrq_n_u32::<16>(vaddq_u32(z, w)))
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn ntt(a: &mut [Zq], twiddles: &[u16]) {
+        forward_layers(a, twiddles, |lo, hi, s| ntt_butterflies(lo, hi, s))
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn intt(a: &mut [Zq], twiddles: &[u16]) {
+        inverse_layers(a, twiddles, |lo, hi, s| intt_butterflies(lo, hi, s))
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn ntt_butterflies(lo: &mut [Zq], hi: &mut [Zq], s: u16) {
+        let sv = vdupq_n_u32(s as u32);
+        let q = vdupq_n_u32(Q);
+        let mut lo_chunks = lo.chunks_exact_mut(4);
+        let mut hi_chunks = hi.chunks_exact_mut(4);
+        for (u, v) in (&mut lo_chunks).zip(&mut hi_chunks) {
+            let a = load_zq(u.as_ptr());
+            let w = mul_montgomery(load_zq(v.as_ptr()), sv);
+            store_zq(u.as_mut_ptr(), reduce(vaddq_u32(a, w)));
+            store_zq(v.as_mut_ptr(), reduce(vsubq_u32(vaddq_u32(a, q), w)));
+        }
+        portable::ntt_butterflies(lo_chunks.into_remainder(), hi_chunks.into_remainder(), s);
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn intt_butterflies(lo: &mut [Zq], hi: &mut [Zq], s: u16) {
+        let sv = vdupq_n_u32(s as u32);
+        let q = vdupq_n_u32(Q);
+        let mut lo_chunks = lo.chunks_exact_mut(4);
+        let mut hi_chunks = hi.chunks_exact_mut(4);
+        for (u, v) in (&mut lo_chunks).zip(&mut hi_chunks) {
+            let a = load_zq(u.as_ptr());
+            let b = load_zq(v.as_ptr());
+            let d = reduce(vsubq_u32(vaddq_u32(a, q), b));
+            store_zq(u.as_mut_ptr(), reduce(vaddq_u32(a, b)));
+            store_zq(v.as_mut_ptr(), mul_montgomery(d, sv));
+        }
+        portable::intt_butterflies(lo_chunks.into_remainder(), hi_chunks.into_remainder(), s);
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn mul_ntt(a: &mut [Zq], b: &[Zq]) {
+        let r2 = vdupq_n_u32(R2);
+        let mut a_chunks = a.chunks_exact_mut(4);
+        let mut b_chunks = b.chunks_exact(4);
+        for (x, y) in (&mut a_chunks).zip(&mut b_chunks) {
+            // x * y / R, then * R^2 / R
+            let xy = mul_montgomery(load_zq(x.as_ptr()), load_zq(y.as_ptr()));
+            store_zq(x.as_mut_ptr(), mul_montgomery(xy, r2));
+        }
+        portable::mul_ntt(a_chunks.into_remainder(), b_chunks.remainder());
+    }
+}
+
+#[cfg(all(test, target_arch = "x86_64"))]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    const LENGTHS: [usize; 7] = [1, 2, 3, 7, 8, 17, 512];
+
+    fn random_complex(n: usize, rng: &mut StdRng) -> Vec<Complex64> {
+        (0..n)
+            .map(|_| Complex64::new(rng.random_range(-1e4..1e4), rng.random_range(-1e4..1e4)))
+            .collect()
+    }
+
+    fn random_zq(n: usize, rng: &mut StdRng) -> Vec<Zq> {
+        (0..n).map(|_| Zq::new(rng.random_range(0..Q))).collect()
+    }
+
+    fn assert_bits_eq(a: &[Complex64], b: &[Complex64]) {
+        for (x, y) in a.iter().zip(b) {
+            assert_eq!(
+                (x.re.to_bits(), x.im.to_bits()),
+                (y.re.to_bits(), y.im.to_bits()),
+                "{x} != {y}"
+            );
+        }
+    }
+
+    /// Skips the test, loudly, on CPUs without AVX2
+    fn skip() -> bool {
+        if !avx2_available() {
+            eprintln!("AVX2 not available, skipping");
+        }
+        !avx2_available()
+    }
+
+    #[test]
+    fn avx2_fft_kernels_match_portable() {
+        if skip() {
+            return;
+        }
+        let mut rng = StdRng::seed_from_u64(0);
+        for n in LENGTHS {
+            let lo = random_complex(n, &mut rng);
+            let hi = random_complex(n, &mut rng);
+            let s = Complex64::from_polar(1., rng.random_range(0.0..std::f64::consts::TAU));
+
+            let (mut lo_p, mut hi_p, mut lo_v, mut hi_v) =
+                (lo.clone(), hi.clone(), lo.clone(), hi.clone());
+            portable::fft_butterflies(&mut lo_p, &mut hi_p, s);
+            unsafe { avx2::fft_butterflies(&mut lo_v, &mut hi_v, s) };
+            assert_bits_eq(&lo_p, &lo_v);
+            assert_bits_eq(&hi_p, &hi_v);
+
+            let (mut lo_p, mut hi_p, mut lo_v, mut hi_v) =
+                (lo.clone(), hi.clone(), lo.clone(), hi.clone());
+            portable::ifft_butterflies(&mut lo_p, &mut hi_p, s);
+            unsafe { avx2::ifft_butterflies(&mut lo_v, &mut hi_v, s) };
+            assert_bits_eq(&lo_p, &lo_v);
+            assert_bits_eq(&hi_p, &hi_v);
+
+            let (mut a_p, mut a_v) = (lo.clone(), lo.clone());
+            portable::mul_fft(&mut a_p, &hi);
+            unsafe { avx2::mul_fft(&mut a_v, &hi) };
+            assert_bits_eq(&a_p, &a_v);
+        }
+    }
+
+    #[test]
+    fn avx2_split_merge_fft_match_portable() {
+        if skip() {
+            return;
+        }
+        let mut rng = StdRng::seed_from_u64(1);
+        for logn in 1..=10 {
+            let n = 1 << logn;
+            let w = crate::fft::roots(n as u16);
+            let f = random_complex(n, &mut rng);
+
+            let mut split_p = (
+                vec![Complex64::default(); n / 2],
+                vec![Complex64::default(); n / 2],
+            );
+            let mut split_v = split_p.clone();
+            portable::split_fft(&f, w, &mut split_p.0, &mut split_p.1);
+            unsafe { avx2::split_fft(&f, w, &mut split_v.0, &mut split_v.1) };
+            assert_bits_eq(&split_p.0, &split_v.0);
+            assert_bits_eq(&split_p.1, &split_v.1);
+
+            let mut merged_p = vec![Complex64::default(); n];
+            let mut merged_v = merged_p.clone();
+            portable::merge_fft(&split_p.0, &split_p.1, w, &mut merged_p);
+            unsafe { avx2::merge_fft(&split_p.0, &split_p.1, w, &mut merged_v) };
+            assert_bits_eq(&merged_p, &merged_v);
+        }
+    }
+
+    #[test]
+    fn avx2_ntt_kernels_match_portable() {
+        if skip() {
+            return;
+        }
+        let mut rng = StdRng::seed_from_u64(2);
+        for n in LENGTHS {
+            let lo = random_zq(n, &mut rng);
+            let mut hi = random_zq(n, &mut rng);
+            // Exercise the edges of the reductions too
+            hi[0] = Zq::ZERO;
+            hi[n - 1] = Zq::new(Q - 1);
+            let s = rng.random_range(0..Q) as u16;
+
+            let (mut lo_p, mut hi_p, mut lo_v, mut hi_v) =
+                (lo.clone(), hi.clone(), lo.clone(), hi.clone());
+            portable::ntt_butterflies(&mut lo_p, &mut hi_p, s);
+            unsafe { avx2::ntt_butterflies(&mut lo_v, &mut hi_v, s) };
+            assert_eq!((lo_p, hi_p), (lo_v, hi_v));
+
+            let (mut lo_p, mut hi_p, mut lo_v, mut hi_v) =
+                (lo.clone(), hi.clone(), lo.clone(), hi.clone());
+            portable::intt_butterflies(&mut lo_p, &mut hi_p, s);
+            unsafe { avx2::intt_butterflies(&mut lo_v, &mut hi_v, s) };
+            assert_eq!((lo_p, hi_p), (lo_v, hi_v));
+
+            let (mut a_p, mut a_v) = (lo.clone(), lo.clone());
+            portable::mul_ntt(&mut a_p, &hi);
+            unsafe { avx2::mul_ntt(&mut a_v, &hi) };
+            assert_eq!(a_p, a_v);
+        }
+    }
+
+    #[test]
+    fn avx2_transforms_match_portable() {
+        if skip() {
+            return;
+        }
+        let mut rng = StdRng::seed_from_u64(4);
+        for logn in 1..=10 {
+            let n = 1 << logn;
+            let a = random_complex(n, &mut rng);
+            let w = random_complex(n, &mut rng);
+            let (mut a_p, mut a_v) = (a.clone(), a.clone());
+            portable::fft(&mut a_p, &w);
+            unsafe { avx2::fft(&mut a_v, &w) };
+            assert_bits_eq(&a_p, &a_v);
+            portable::ifft(&mut a_p, &w);
+            unsafe { avx2::ifft(&mut a_v, &w) };
+            assert_bits_eq(&a_p, &a_v);
+
+            let a = random_zq(n, &mut rng);
+            let w: Vec<u16> = (0..n).map(|_| rng.random_range(0..Q) as u16).collect();
+            let (mut a_p, mut a_v) = (a.clone(), a.clone());
+            portable::ntt(&mut a_p, &w);
+            unsafe { avx2::ntt(&mut a_v, &w) };
+            assert_eq!(a_p, a_v);
+            portable::intt(&mut a_p, &w);
+            unsafe { avx2::intt(&mut a_v, &w) };
+            assert_eq!(a_p, a_v);
+        }
+    }
+
+    /// Whole transforms, portable against the dispatched kernels. Run with
+    /// `cargo test --release -- --ignored --nocapture bench_transforms`
+    #[test]
+    #[ignore]
+    fn bench_transforms() {
+        use std::hint::black_box;
+        use std::time::Instant;
+
+        const ITERATIONS: u32 = 10000;
+        fn time(mut transform: impl FnMut()) -> std::time::Duration {
+            let start = Instant::now();
+            for _ in 0..ITERATIONS {
+                transform();
+            }
+            start.elapsed() / ITERATIONS
+        }
+
+        let mut rng = StdRng::seed_from_u64(3);
+        for n in [512, 1024] {
+            // Any twiddles will do for timing
+            let a = random_complex(n, &mut rng);
+            let w = random_complex(n, &mut rng);
+            let portable = time(|| portable::fft(black_box(&mut a.clone()), &w));
+            let dispatched = time(|| fft(black_box(&mut a.clone()), &w));
+            println!("fft, n = {n}: portable {portable:?}, dispatched {dispatched:?}");
+            let portable = time(|| portable::ifft(black_box(&mut a.clone()), &w));
+            let dispatched = time(|| ifft(black_box(&mut a.clone()), &w));
+            println!("ifft, n = {n}: portable {portable:?}, dispatched {dispatched:?}");
+
+            let a = random_zq(n, &mut rng);
+            let w: Vec<u16> = (0..n).map(|_| rng.random_range(0..Q) as u16).collect();
+            let portable = time(|| portable::ntt(black_box(&mut a.clone()), &w));
+            let dispatched = time(|| ntt(black_box(&mut a.clone()), &w));
+            println!("ntt, n = {n}: portable {portable:?}, dispatched {dispatched:?}");
+            let portable = time(|| portable::intt(black_box(&mut a.clone()), &w));
+            let dispatched = time(|| intt(black_box(&mut a.clone()), &w));
+            println!("intt, n = {n}: portable {portable:?}, dispatched {dispatched:?}");
+        }
+    }
+}
@@ -0,0 +1,176 @@
+#![allow(dead_code)]
+
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::common::Q;
+
+/// Element of the field Z/qZ, always kept in its canonical representative [0, q)
+///
+/// Transparent over `u16` so that the SIMD kernels can load slices of it directly
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct Zq(u16);
+
+/// -q^-1 mod 2^16, for Montgomery reduction
+pub(crate) const Q0I: u32 = 12287;
+
+impl Zq {
+    pub const ZERO: Self = Self(0);
+    pub const ONE: Self = Self(1);
+
+    pub const fn new(value: u32) -> Self {
+        Self((value % Q) as u16)
+    }
+
+    pub const fn value(self) -> u32 {
+        self.0 as u32
+    }
+
+    /// Representative in (-q/2, q/2]
+    pub const fn centered(self) -> i16 {
+        if self.value() > Q / 2 {
+            self.0 as i16 - Q as i16
+        } else {
+            self.0 as i16
+        }
+    }
+
+    /// self * other / 2^16 mod q, multiplying by a factor given in Montgomery form
+    pub fn mul_montgomery(self, other: u16) -> Self {
+        let z = self.value() * other as u32;
+        let w = (z.wrapping_mul(Q0I) & 0xFFFF) * Q;
+        let z = (z + w) >> 16;
+        Self(if z >= Q { z - Q } else { z } as u16)
+    }
+
+    pub fn pow(self, mut exponent: u32) -> Self {
+        let mut base = self;
+        let mut result = Self::ONE;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result * base;
+            }
+            base = base * base;
+            exponent >>= 1;
+        }
+        result
+    }
+
+    /// Multiplicative inverse, by Fermat's little theorem
+    pub fn inverse(self) -> Option<Self> {
+        if self == Self::ZERO {
+            return None;
+        }
+        Some(self.pow(Q - 2))
+    }
+}
+
+impl From<i16> for Zq {
+    fn from(value: i16) -> Self {
+        Self((value as i32).rem_euclid(Q as i32) as u16)
+    }
+}
+
+impl From<Zq> for f64 {
+    fn from(value: Zq) -> Self {
+        value.0 as f64
+    }
+}
+
+impl Add for Zq {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let sum = self.value() + other.value();
+        Self(if sum >= Q { sum - Q } else { sum } as u16)
+    }
+}
+
+impl Neg for Zq {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(if self.0 == 0 { 0 } else { Q - self.value() } as u16)
+    }
+}
+
+impl Sub for Zq {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self + (-other)
+    }
+}
+
+impl Mul for Zq {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self::new(self.value() * other.value())
+    }
+}
+
+impl Div for Zq {
+    type Output = Self;
+
+    /// Panics on division by zero, like integer division
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, other: Self) -> Self {
+        self * other.inverse().expect("division by zero in Z/qZ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn centered_representatives() {
+        assert_eq!(Zq::new(0).centered(), 0);
+        assert_eq!(Zq::new(6144).centered(), 6144);
+        assert_eq!(Zq::new(6145).centered(), -6144);
+        assert_eq!(Zq::new(Q - 1).centered(), -1);
+    }
+
+    #[test]
+    fn from_negative_values() {
+        assert_eq!(Zq::from(-1), Zq::new(Q - 1));
+        assert_eq!(Zq::from(-6144), Zq::new(6145));
+        assert_eq!(Zq::from(i16::MIN), Zq::new(Q - 32768 % Q));
+        assert_eq!(Zq::from(-(Q as i16)), Zq::ZERO);
+        for value in -6144..=6144 {
+            assert_eq!(Zq::from(value).centered(), value);
+        }
+    }
+
+    #[test]
+    fn negation_and_inverse() {
+        assert_eq!(-Zq::ZERO, Zq::ZERO);
+        assert_eq!(-Zq::ONE, Zq::new(Q - 1));
+        assert_eq!(Zq::ZERO.inverse(), None);
+        assert_eq!(Zq::ONE.inverse(), Some(Zq::ONE));
+        for value in 1..Q {
+            let x = Zq::new(value);
+            assert_eq!(x + (-x), Zq::ZERO);
+            assert_eq!(x * x.inverse().unwrap(), Zq::ONE);
+        }
+    }
+
+    #[test]
+    fn montgomery_multiplication_matches_mul() {
+        // 2^16 mod q, the Montgomery form of one
+        let r = Zq::new(1 << 16);
+        let mut rng = StdRng::seed_from_u64(0);
+        let edges = [0, 1, Q - 1, Q / 2];
+        let samples = (0..10000).map(|_| (rng.random_range(0..Q), rng.random_range(0..Q)));
+        let pairs = edges.iter().flat_map(|&a| edges.map(|b| (a, b)));
+        for (a, b) in pairs.chain(samples) {
+            let (a, b) = (Zq::new(a), Zq::new(b));
+            let b_montgomery = (b * r).value() as u16;
+            assert_eq!(a.mul_montgomery(b_montgomery), a * b, "{a:?} * {b:?}");
+        }
+    }
+}